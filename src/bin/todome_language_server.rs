@@ -24,20 +24,14 @@ async fn main() -> Result<()> {
         let listener = TcpListener::bind(format!("127.0.0.1:{}", opts.port)).await?;
         let (stream, _) = listener.accept().await?;
         let (read, write) = tokio::io::split(stream);
-        let (service, messages) = LspService::new(todome::language_server::LanguageServer::new);
-        Server::new(read, write)
-            .interleave(messages)
-            .serve(service)
-            .await;
+        let (service, socket) = LspService::new(todome::language_server::LanguageServer::new);
+        Server::new(read, write, socket).serve(service).await;
     } else {
         let stdin = tokio::io::stdin();
         let stdout = tokio::io::stdout();
 
-        let (service, messages) = LspService::new(todome::language_server::LanguageServer::new);
-        Server::new(stdin, stdout)
-            .interleave(messages)
-            .serve(service)
-            .await;
+        let (service, socket) = LspService::new(todome::language_server::LanguageServer::new);
+        Server::new(stdin, stdout, socket).serve(service).await;
     }
     Ok(())
 }
@@ -1,5 +1,9 @@
-use std::sync::Arc;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 
+use itertools::Itertools;
 use log::{debug, error, info, warn};
 use tower_lsp::{
     jsonrpc::ErrorCode,
@@ -7,11 +11,36 @@ use tower_lsp::{
     Client,
 };
 
-use crate::structure::syntax::DocumentCache;
+use crate::{
+    structure::{
+        position::PosInto,
+        syntax::{Document, DocumentCache},
+    },
+    subcmd::format::FormatOptions,
+};
+
+use self::config::Config;
 
+mod archive;
 mod capabilities;
+mod clock;
+mod code_action;
+mod code_lens;
 mod completion;
+mod config;
+mod definition;
 mod diagnostics;
+mod folding;
+mod formatting;
+mod hover;
+mod inlay_hint;
+mod occurrence;
+mod progress;
+mod references;
+mod rename;
+mod semantic_tokens;
+mod symbol;
+mod workspace;
 
 #[derive(Debug, Clone)]
 pub struct LanguageServer(Arc<tokio::sync::Mutex<Inner>>);
@@ -24,6 +53,25 @@ impl LanguageServer {
     fn inner(&self) -> &Arc<tokio::sync::Mutex<Inner>> {
         &self.0
     }
+
+    /// `roots` 以下の `.todome` ファイルをバックグラウンドで索引に加える。
+    fn index_workspace(&self, roots: Vec<tower_lsp::lsp_types::Url>) {
+        let inner = self.inner().clone();
+        tokio::spawn(Inner::index_workspace(inner, roots));
+    }
+
+    /// 日付が変わるたびに diagnostics を送り直す。
+    fn watch_clock(&self) {
+        let inner = self.inner().clone();
+        tokio::spawn(async move {
+            let mut clock = clock::Clock::start();
+            loop {
+                clock.changed().await;
+                info!("Date changed; refreshing diagnostics");
+                inner.lock().await.republish_diagnostics().await;
+            }
+        });
+    }
 }
 
 #[tower_lsp::async_trait]
@@ -35,10 +83,81 @@ impl tower_lsp::LanguageServer for LanguageServer {
         self.inner().lock().await.initialize(params).await
     }
 
+    async fn initialized(&self, params: tower_lsp::lsp_types::InitializedParams) {
+        let roots = {
+            let mut inner = self.inner().lock().await;
+            inner.initialized(params).await;
+            inner.workspace_roots.clone()
+        };
+        self.index_workspace(roots);
+        self.watch_clock();
+    }
+
     async fn shutdown(&self) -> tower_lsp::jsonrpc::Result<()> {
         Ok(())
     }
 
+    async fn did_change_workspace_folders(
+        &self,
+        params: tower_lsp::lsp_types::DidChangeWorkspaceFoldersParams,
+    ) {
+        let added = params
+            .event
+            .added
+            .iter()
+            .map(|folder| folder.uri.clone())
+            .collect();
+        self.inner()
+            .lock()
+            .await
+            .did_change_workspace_folders(params)
+            .await;
+        self.index_workspace(added);
+    }
+
+    async fn did_change_configuration(
+        &self,
+        params: tower_lsp::lsp_types::DidChangeConfigurationParams,
+    ) {
+        self.inner()
+            .lock()
+            .await
+            .did_change_configuration(params)
+            .await;
+    }
+
+    async fn diagnostic(
+        &self,
+        params: tower_lsp::lsp_types::DocumentDiagnosticParams,
+    ) -> tower_lsp::jsonrpc::Result<tower_lsp::lsp_types::DocumentDiagnosticReportResult> {
+        self.inner().lock().await.diagnostic(params).await
+    }
+
+    async fn workspace_diagnostic(
+        &self,
+        params: tower_lsp::lsp_types::WorkspaceDiagnosticParams,
+    ) -> tower_lsp::jsonrpc::Result<tower_lsp::lsp_types::WorkspaceDiagnosticReportResult> {
+        self.inner().lock().await.workspace_diagnostic(params).await
+    }
+
+    async fn did_change_watched_files(
+        &self,
+        params: tower_lsp::lsp_types::DidChangeWatchedFilesParams,
+    ) {
+        self.inner()
+            .lock()
+            .await
+            .did_change_watched_files(params)
+            .await;
+    }
+
+    async fn symbol(
+        &self,
+        params: tower_lsp::lsp_types::WorkspaceSymbolParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<Vec<tower_lsp::lsp_types::SymbolInformation>>> {
+        self.inner().lock().await.symbol(params).await
+    }
+
     async fn did_open(&self, params: tower_lsp::lsp_types::DidOpenTextDocumentParams) {
         self.inner().lock().await.did_open(params).await;
     }
@@ -60,6 +179,132 @@ impl tower_lsp::LanguageServer for LanguageServer {
     ) -> tower_lsp::jsonrpc::Result<Option<tower_lsp::lsp_types::CompletionResponse>> {
         self.inner().lock().await.completion(params).await
     }
+
+    async fn hover(
+        &self,
+        params: tower_lsp::lsp_types::HoverParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<tower_lsp::lsp_types::Hover>> {
+        self.inner().lock().await.hover(params).await
+    }
+
+    async fn code_action(
+        &self,
+        params: tower_lsp::lsp_types::CodeActionParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<tower_lsp::lsp_types::CodeActionResponse>> {
+        self.inner().lock().await.code_action(params).await
+    }
+
+    async fn formatting(
+        &self,
+        params: tower_lsp::lsp_types::DocumentFormattingParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<Vec<tower_lsp::lsp_types::TextEdit>>> {
+        self.inner().lock().await.formatting(params).await
+    }
+
+    async fn on_type_formatting(
+        &self,
+        params: tower_lsp::lsp_types::DocumentOnTypeFormattingParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<Vec<tower_lsp::lsp_types::TextEdit>>> {
+        self.inner().lock().await.on_type_formatting(params).await
+    }
+
+    async fn code_lens(
+        &self,
+        params: tower_lsp::lsp_types::CodeLensParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<Vec<tower_lsp::lsp_types::CodeLens>>> {
+        self.inner().lock().await.code_lens(params).await
+    }
+
+    async fn execute_command(
+        &self,
+        params: tower_lsp::lsp_types::ExecuteCommandParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<serde_json::Value>> {
+        self.inner().lock().await.execute_command(params).await
+    }
+
+    async fn inlay_hint(
+        &self,
+        params: tower_lsp::lsp_types::InlayHintParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<Vec<tower_lsp::lsp_types::InlayHint>>> {
+        self.inner().lock().await.inlay_hint(params).await
+    }
+
+    async fn goto_definition(
+        &self,
+        params: tower_lsp::lsp_types::GotoDefinitionParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<tower_lsp::lsp_types::GotoDefinitionResponse>> {
+        self.inner().lock().await.goto_definition(params).await
+    }
+
+    async fn goto_declaration(
+        &self,
+        params: tower_lsp::lsp_types::request::GotoDeclarationParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<tower_lsp::lsp_types::request::GotoDeclarationResponse>>
+    {
+        // タスクの属性には宣言と定義の区別が無いので、定義と同じものを返す。
+        self.inner().lock().await.goto_definition(params).await
+    }
+
+    async fn references(
+        &self,
+        params: tower_lsp::lsp_types::ReferenceParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<Vec<tower_lsp::lsp_types::Location>>> {
+        self.inner().lock().await.references(params).await
+    }
+
+    async fn document_highlight(
+        &self,
+        params: tower_lsp::lsp_types::DocumentHighlightParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<Vec<tower_lsp::lsp_types::DocumentHighlight>>> {
+        self.inner().lock().await.document_highlight(params).await
+    }
+
+    async fn prepare_rename(
+        &self,
+        params: tower_lsp::lsp_types::TextDocumentPositionParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<tower_lsp::lsp_types::PrepareRenameResponse>> {
+        self.inner().lock().await.prepare_rename(params).await
+    }
+
+    async fn rename(
+        &self,
+        params: tower_lsp::lsp_types::RenameParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<tower_lsp::lsp_types::WorkspaceEdit>> {
+        self.inner().lock().await.rename(params).await
+    }
+
+    async fn semantic_tokens_full(
+        &self,
+        params: tower_lsp::lsp_types::SemanticTokensParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<tower_lsp::lsp_types::SemanticTokensResult>> {
+        self.inner().lock().await.semantic_tokens_full(params).await
+    }
+
+    async fn semantic_tokens_full_delta(
+        &self,
+        params: tower_lsp::lsp_types::SemanticTokensDeltaParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<tower_lsp::lsp_types::SemanticTokensFullDeltaResult>>
+    {
+        self.inner()
+            .lock()
+            .await
+            .semantic_tokens_full_delta(params)
+            .await
+    }
+
+    async fn folding_range(
+        &self,
+        params: tower_lsp::lsp_types::FoldingRangeParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<Vec<tower_lsp::lsp_types::FoldingRange>>> {
+        self.inner().lock().await.folding_range(params).await
+    }
+
+    async fn document_symbol(
+        &self,
+        params: tower_lsp::lsp_types::DocumentSymbolParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<tower_lsp::lsp_types::DocumentSymbolResponse>> {
+        self.inner().lock().await.document_symbol(params).await
+    }
 }
 
 #[derive(Debug)]
@@ -67,6 +312,31 @@ pub struct Inner {
     /// The LSP client that this LSP server is connected to.
     client: Client,
     document_cache: DocumentCache,
+    config: Config,
+    /// ワークスペースのルートディレクトリ。
+    workspace_roots: Vec<tower_lsp::lsp_types::Url>,
+    /// ワークスペース内の、開かれていないものも含めた `.todome` ファイル。
+    workspace_index: workspace::WorkspaceIndex,
+    /// クライアントが `$/progress` に対応しているかどうか。
+    work_done_progress: bool,
+    /// クライアントがファイルの監視を動的に登録できるかどうか。
+    watch_files: bool,
+    /// クライアントが `workspace/configuration` で設定を返せるかどうか。
+    pull_configuration: bool,
+    /// クライアントが設定の変更の通知を動的に登録できるかどうか。
+    watch_configuration: bool,
+    /// クライアントが pull 型の diagnostics に対応しているかどうか。対応していれば diagnostics を送らない。
+    pull_diagnostics: bool,
+    /// クライアントに diagnostics の問い合わせ直しを頼めるかどうか。
+    diagnostic_refresh: bool,
+    /// クライアントが `WorkspaceEdit` の `documentChanges` でファイルを作れるかどうか。
+    create_files: bool,
+    /// diagnostics を送ったドキュメント。開いていないものも含め、日付が変わったときに送り直す。
+    published_diagnostics: HashSet<tower_lsp::lsp_types::Url>,
+    /// 最後に送った semantic tokens。delta の計算に使う。
+    semantic_tokens: HashMap<tower_lsp::lsp_types::Url, tower_lsp::lsp_types::SemanticTokens>,
+    /// semantic tokens の result id を振るためのカウンタ。
+    semantic_tokens_id: u64,
 }
 
 impl Inner {
@@ -74,14 +344,68 @@ impl Inner {
         Self {
             client,
             document_cache: DocumentCache::default(),
+            config: Config::default(),
+            workspace_roots: vec![],
+            workspace_index: workspace::WorkspaceIndex::default(),
+            work_done_progress: false,
+            watch_files: false,
+            pull_configuration: false,
+            watch_configuration: false,
+            pull_diagnostics: false,
+            diagnostic_refresh: false,
+            create_files: false,
+            published_diagnostics: HashSet::new(),
+            semantic_tokens: HashMap::new(),
+            semantic_tokens_id: 0,
         }
     }
 
     async fn initialize(
-        &self,
+        &mut self,
         params: tower_lsp::lsp_types::InitializeParams,
     ) -> tower_lsp::jsonrpc::Result<InitializeResult> {
         let capabilities = capabilities::server_capabilities(&params.capabilities);
+        self.workspace_roots = workspace::workspace_roots(&params);
+        self.work_done_progress = params
+            .capabilities
+            .window
+            .as_ref()
+            .and_then(|window| window.work_done_progress)
+            .unwrap_or(false);
+        self.watch_files = params
+            .capabilities
+            .workspace
+            .as_ref()
+            .and_then(|workspace| workspace.did_change_watched_files.as_ref())
+            .and_then(|watched| watched.dynamic_registration)
+            .unwrap_or(false);
+        self.pull_diagnostics = capabilities::supports_pull_diagnostics(&params.capabilities);
+        let workspace_capabilities = params.capabilities.workspace.as_ref();
+        self.diagnostic_refresh = workspace_capabilities
+            .and_then(|workspace| workspace.diagnostic.as_ref())
+            .and_then(|diagnostic| diagnostic.refresh_support)
+            .unwrap_or(false);
+        self.pull_configuration = workspace_capabilities
+            .and_then(|workspace| workspace.configuration)
+            .unwrap_or(false);
+        self.watch_configuration = workspace_capabilities
+            .and_then(|workspace| workspace.did_change_configuration.as_ref())
+            .and_then(|configuration| configuration.dynamic_registration)
+            .unwrap_or(false);
+        self.create_files = workspace_capabilities
+            .and_then(|workspace| workspace.workspace_edit.as_ref())
+            .is_some_and(|edit| {
+                edit.document_changes == Some(true)
+                    && edit.resource_operations.as_ref().is_some_and(|operations| {
+                        operations.contains(&tower_lsp::lsp_types::ResourceOperationKind::Create)
+                    })
+            });
+        if let Some(options) = params.initialization_options {
+            match Config::from_value(options) {
+                Ok(config) => self.config = config,
+                Err(e) => error!("Invalid initialization options: {}", e),
+            }
+        }
         let server_info = ServerInfo {
             name: "todome-language-server".to_owned(),
             version: Some(crate::version()),
@@ -101,14 +425,262 @@ impl Inner {
         })
     }
 
+    async fn initialized(&mut self, _: tower_lsp::lsp_types::InitializedParams) {
+        info!("called initialized");
+        if self.pull_configuration {
+            self.pull_config().await;
+        }
+
+        let mut registrations = vec![];
+        if self.watch_files {
+            let options = tower_lsp::lsp_types::DidChangeWatchedFilesRegistrationOptions {
+                watchers: vec![tower_lsp::lsp_types::FileSystemWatcher {
+                    glob_pattern: tower_lsp::lsp_types::GlobPattern::String(
+                        "**/*.todome".to_owned(),
+                    ),
+                    kind: None,
+                }],
+            };
+            registrations.push(tower_lsp::lsp_types::Registration {
+                id: "todome/didChangeWatchedFiles".to_owned(),
+                method: "workspace/didChangeWatchedFiles".to_owned(),
+                register_options: serde_json::to_value(options).ok(),
+            });
+        }
+        if self.watch_configuration {
+            registrations.push(tower_lsp::lsp_types::Registration {
+                id: "todome/didChangeConfiguration".to_owned(),
+                method: "workspace/didChangeConfiguration".to_owned(),
+                register_options: None,
+            });
+        }
+        if registrations.is_empty() {
+            return;
+        }
+        if let Err(e) = self.client.register_capability(registrations).await {
+            error!("Failed to register capabilities: {}", e);
+        }
+    }
+
+    async fn did_change_configuration(
+        &mut self,
+        params: tower_lsp::lsp_types::DidChangeConfigurationParams,
+    ) {
+        info!("called did_change_configuration");
+        // pull に対応したクライアントは通知に設定を載せないことが多いので、改めて問い合わせる。
+        if self.pull_configuration {
+            self.pull_config().await;
+            return;
+        }
+        match Config::from_value(params.settings) {
+            Ok(config) => self.update_config(config).await,
+            Err(e) => error!("Invalid configuration: {}", e),
+        }
+    }
+
+    /// `workspace/configuration` で `todome` セクションの設定を問い合わせる。
+    async fn pull_config(&mut self) {
+        let item = tower_lsp::lsp_types::ConfigurationItem {
+            scope_uri: None,
+            section: Some("todome".to_owned()),
+        };
+        let value = match self.client.configuration(vec![item]).await {
+            Ok(mut values) if !values.is_empty() => values.remove(0),
+            Ok(_) => return,
+            Err(e) => {
+                error!("Failed to fetch configuration: {}", e);
+                return;
+            }
+        };
+        match Config::from_value(value) {
+            Ok(config) => self.update_config(config).await,
+            Err(e) => error!("Invalid configuration: {}", e),
+        }
+    }
+
+    /// 設定を差し替え、diagnostics に関わる設定が変われば送り直す。
+    async fn update_config(&mut self, config: Config) {
+        debug!("config: {:#?}", config);
+        let changed =
+            config.diagnostics != self.config.diagnostics || config.today != self.config.today;
+        self.config = config;
+        if changed {
+            self.republish_diagnostics().await;
+        }
+    }
+
+    /// これまでに diagnostics を送ったドキュメントについて送り直す。
+    /// pull 型のクライアントには、代わりに問い合わせ直しを頼む。
+    async fn republish_diagnostics(&self) {
+        if self.pull_diagnostics {
+            self.refresh_diagnostics().await;
+            return;
+        }
+        let today = self.config.today();
+        for url in &self.published_diagnostics {
+            let document = self
+                .document_cache
+                .get(url)
+                .or_else(|| self.workspace_index.get(url));
+            if let Some(document) = document {
+                let diags = document.get_diagnostics(today, &self.config.diagnostics);
+                self.client
+                    .publish_diagnostics(url.clone(), diags, None)
+                    .await;
+            }
+        }
+    }
+
+    /// diagnostics を送る。pull 型のクライアントには送らず、問い合わせを待つ。
+    async fn publish_diagnostics(
+        &mut self,
+        url: tower_lsp::lsp_types::Url,
+        diags: Vec<tower_lsp::lsp_types::Diagnostic>,
+    ) {
+        if !self.pull_diagnostics {
+            self.published_diagnostics.insert(url.clone());
+            self.client.publish_diagnostics(url, diags, None).await;
+        }
+    }
+
+    /// 開いても索引してもいないドキュメントの diagnostics を消す。以後は送り直さない。
+    async fn clear_diagnostics(&mut self, url: tower_lsp::lsp_types::Url) {
+        if self.published_diagnostics.remove(&url) {
+            self.client.publish_diagnostics(url, vec![], None).await;
+        }
+    }
+
+    /// pull 型のクライアントに、diagnostics を問い合わせ直すよう頼む。
+    async fn refresh_diagnostics(&self) {
+        if !self.pull_diagnostics || !self.diagnostic_refresh {
+            return;
+        }
+        if let Err(e) = self.client.workspace_diagnostic_refresh().await {
+            error!("Failed to refresh diagnostics: {}", e);
+        }
+    }
+
+    async fn diagnostic(
+        &mut self,
+        params: tower_lsp::lsp_types::DocumentDiagnosticParams,
+    ) -> tower_lsp::jsonrpc::Result<tower_lsp::lsp_types::DocumentDiagnosticReportResult> {
+        let url = &params.text_document.uri;
+        let document = self
+            .document_cache
+            .get(url)
+            .or_else(|| self.workspace_index.get(url));
+        let report = match document {
+            Some(document) => document.get_diagnostic_report(
+                self.config.today(),
+                &self.config.diagnostics,
+                params.previous_result_id.as_deref(),
+            ),
+            None => {
+                warn!("Document not found.");
+                tower_lsp::lsp_types::DocumentDiagnosticReportKind::Full(Default::default())
+            }
+        };
+        let report = match report {
+            tower_lsp::lsp_types::DocumentDiagnosticReportKind::Full(report) => {
+                tower_lsp::lsp_types::DocumentDiagnosticReport::Full(
+                    tower_lsp::lsp_types::RelatedFullDocumentDiagnosticReport {
+                        related_documents: None,
+                        full_document_diagnostic_report: report,
+                    },
+                )
+            }
+            tower_lsp::lsp_types::DocumentDiagnosticReportKind::Unchanged(report) => {
+                tower_lsp::lsp_types::DocumentDiagnosticReport::Unchanged(
+                    tower_lsp::lsp_types::RelatedUnchangedDocumentDiagnosticReport {
+                        related_documents: None,
+                        unchanged_document_diagnostic_report: report,
+                    },
+                )
+            }
+        };
+        Ok(tower_lsp::lsp_types::DocumentDiagnosticReportResult::Report(report))
+    }
+
+    /// 開かれていないファイルも含め、ワークスペース内のすべてのファイルの diagnostics。
+    async fn workspace_diagnostic(
+        &mut self,
+        params: tower_lsp::lsp_types::WorkspaceDiagnosticParams,
+    ) -> tower_lsp::jsonrpc::Result<tower_lsp::lsp_types::WorkspaceDiagnosticReportResult> {
+        let previous = params
+            .previous_result_ids
+            .into_iter()
+            .map(|previous| (previous.uri, previous.value))
+            .collect::<HashMap<_, _>>();
+        let today = self.config.today();
+        let items = self
+            .workspace_documents()
+            .into_iter()
+            .map(|(url, document)| {
+                let report = document.get_diagnostic_report(
+                    today,
+                    &self.config.diagnostics,
+                    previous.get(&url).map(String::as_str),
+                );
+                match report {
+                    tower_lsp::lsp_types::DocumentDiagnosticReportKind::Full(report) => {
+                        tower_lsp::lsp_types::WorkspaceDocumentDiagnosticReport::Full(
+                            tower_lsp::lsp_types::WorkspaceFullDocumentDiagnosticReport {
+                                version: self.document_cache.version(&url).map(i64::from),
+                                uri: url,
+                                full_document_diagnostic_report: report,
+                            },
+                        )
+                    }
+                    tower_lsp::lsp_types::DocumentDiagnosticReportKind::Unchanged(report) => {
+                        tower_lsp::lsp_types::WorkspaceDocumentDiagnosticReport::Unchanged(
+                            tower_lsp::lsp_types::WorkspaceUnchangedDocumentDiagnosticReport {
+                                version: self.document_cache.version(&url).map(i64::from),
+                                uri: url,
+                                unchanged_document_diagnostic_report: report,
+                            },
+                        )
+                    }
+                }
+            })
+            .collect();
+        let report = tower_lsp::lsp_types::WorkspaceDiagnosticReport { items };
+        Ok(tower_lsp::lsp_types::WorkspaceDiagnosticReportResult::Report(report))
+    }
+
+    async fn did_change_workspace_folders(
+        &mut self,
+        params: tower_lsp::lsp_types::DidChangeWorkspaceFoldersParams,
+    ) {
+        info!("called did_change_workspace_folders");
+        let event = params.event;
+        let removed = event
+            .removed
+            .iter()
+            .flat_map(|folder| self.workspace_index.remove_folder(&folder.uri))
+            .collect::<Vec<_>>();
+        self.workspace_roots
+            .retain(|root| !event.removed.iter().any(|folder| &folder.uri == root));
+        self.workspace_roots
+            .extend(event.added.into_iter().map(|folder| folder.uri));
+
+        // 開かれているものを除き、外されたフォルダのファイルの diagnostics を消す。
+        for url in removed {
+            if self.document_cache.get(&url).is_none() {
+                self.clear_diagnostics(url).await;
+            }
+        }
+        self.refresh_diagnostics().await;
+    }
+
     async fn did_open(&mut self, params: tower_lsp::lsp_types::DidOpenTextDocumentParams) {
         info!("called did_open");
         let url = params.text_document.uri;
         let text = params.text_document.text;
-        match self.document_cache.register_or_update(&url, text) {
+        let version = params.text_document.version;
+        match self.document_cache.register_or_update(&url, text, version) {
             Ok(document) => {
-                let diags = document.get_diagnostics();
-                self.client.publish_diagnostics(url, diags, None).await;
+                let diags = document.get_diagnostics(self.config.today(), &self.config.diagnostics);
+                self.publish_diagnostics(url, diags).await;
             }
             Err(e) => {
                 error!("Failed to register document {}", url);
@@ -117,21 +689,23 @@ impl Inner {
         }
     }
 
-    async fn did_change(&mut self, mut params: tower_lsp::lsp_types::DidChangeTextDocumentParams) {
+    async fn did_change(&mut self, params: tower_lsp::lsp_types::DidChangeTextDocumentParams) {
         info!("called did_change");
         let url = params.text_document.uri;
-        // full changes を仮定
-        if params.content_changes.get(0).is_some() {
-            let text = params.content_changes.swap_remove(0).text;
-            match self.document_cache.register_or_update(&url, text) {
-                Ok(document) => {
-                    let diags = document.get_diagnostics();
-                    self.client.publish_diagnostics(url, diags, None).await;
-                }
-                Err(e) => {
-                    error!("Failed to register document {}", url);
-                    error!("{}", e);
-                }
+        let version = params.text_document.version;
+        match self
+            .document_cache
+            .apply_changes(&url, params.content_changes, version)
+        {
+            Ok(document) => {
+                let diags = document.get_diagnostics(self.config.today(), &self.config.diagnostics);
+                self.publish_diagnostics(url, diags).await;
+            }
+            Err(e) => {
+                // 内容がクライアントと食い違うので、開き直されるまでドキュメントを扱わない。
+                error!("Dropped document {} after a failed update", url);
+                error!("{}", e);
+                self.semantic_tokens.remove(&url);
             }
         }
     }
@@ -141,13 +715,68 @@ impl Inner {
         let url = params.text_document.uri;
         if let Some(document) = self.document_cache.get(&url) {
             debug!("{}", document);
-            let diags = document.get_diagnostics();
-            self.client.publish_diagnostics(url, diags, None).await;
+            let diags = document.get_diagnostics(self.config.today(), &self.config.diagnostics);
+            self.publish_diagnostics(url, diags).await;
         }
     }
 
-    async fn did_close(&mut self, _params: tower_lsp::lsp_types::DidCloseTextDocumentParams) {
+    async fn did_close(&mut self, params: tower_lsp::lsp_types::DidCloseTextDocumentParams) {
         info!("called did_close");
+        let url = params.text_document.uri;
+        self.document_cache.remove(&url);
+        self.semantic_tokens.remove(&url);
+        // 保存されていない変更は捨てられるので、ディスク上の内容に戻す。
+        // ワークスペースの外のファイルは索引に入れない。
+        if workspace::is_in_workspace(&self.workspace_roots, &url)
+            || self.workspace_index.get(&url).is_some()
+        {
+            self.reload_from_disk(url).await;
+        } else {
+            self.workspace_index.remove(&url);
+            self.clear_diagnostics(url).await;
+        }
+        self.refresh_diagnostics().await;
+    }
+
+    async fn did_change_watched_files(
+        &mut self,
+        params: tower_lsp::lsp_types::DidChangeWatchedFilesParams,
+    ) {
+        info!("called did_change_watched_files");
+        for change in params.changes {
+            // 開かれているドキュメントはクライアントの内容を正とする。
+            if self.document_cache.get(&change.uri).is_some() {
+                continue;
+            }
+            if change.typ == tower_lsp::lsp_types::FileChangeType::DELETED {
+                self.workspace_index.remove(&change.uri);
+                self.clear_diagnostics(change.uri).await;
+            } else {
+                self.reload_from_disk(change.uri).await;
+            }
+        }
+        self.refresh_diagnostics().await;
+    }
+
+    /// ディスク上のファイルを読み直して索引を更新し、diagnostics を送り直す。
+    /// 読めなければ索引から取り除き、diagnostics を消す。
+    async fn reload_from_disk(&mut self, url: tower_lsp::lsp_types::Url) {
+        let document = url
+            .to_file_path()
+            .map_err(|_| anyhow::anyhow!("not a file: {}", url))
+            .and_then(|path| workspace::load_document(&path));
+        match document {
+            Ok(document) => {
+                let diags = document.get_diagnostics(self.config.today(), &self.config.diagnostics);
+                self.workspace_index.insert(url.clone(), document);
+                self.publish_diagnostics(url, diags).await;
+            }
+            Err(e) => {
+                debug!("Failed to read {}: {}", url, e);
+                self.workspace_index.remove(&url);
+                self.clear_diagnostics(url).await;
+            }
+        }
     }
 
     async fn completion(
@@ -156,19 +785,574 @@ impl Inner {
     ) -> tower_lsp::jsonrpc::Result<Option<tower_lsp::lsp_types::CompletionResponse>> {
         let url = params.text_document_position.text_document.uri.clone();
         if let Some(document) = self.document_cache.get(&url) {
-            let completions =
+            let workspace = self
+                .workspace_documents()
+                .into_iter()
+                .filter(|(other, _)| other != &url)
+                .map(|(_, document)| document)
+                .collect::<Vec<_>>();
+            let completions = document
+                .get_completion(
+                    &params,
+                    &workspace,
+                    &self.config.completion,
+                    self.config.today(),
+                )
+                .map_err(|e| tower_lsp::jsonrpc::Error {
+                    code: ErrorCode::InternalError,
+                    message: format!("{}", e).into(),
+                    data: None,
+                })?;
+            debug!("completions: {:#?}", completions);
+            Ok(Some(CompletionResponse::Array(completions)))
+        } else {
+            warn!("Document not found.");
+            Ok(None)
+        }
+    }
+
+    async fn semantic_tokens_full(
+        &mut self,
+        params: tower_lsp::lsp_types::SemanticTokensParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<tower_lsp::lsp_types::SemanticTokensResult>> {
+        let url = params.text_document.uri;
+        match self.compute_semantic_tokens(&url) {
+            Some(tokens) => Ok(Some(tokens.into())),
+            None => {
+                warn!("Document not found.");
+                Ok(None)
+            }
+        }
+    }
+
+    async fn semantic_tokens_full_delta(
+        &mut self,
+        params: tower_lsp::lsp_types::SemanticTokensDeltaParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<tower_lsp::lsp_types::SemanticTokensFullDeltaResult>>
+    {
+        let url = params.text_document.uri;
+        let previous = self
+            .semantic_tokens
+            .get(&url)
+            .filter(|tokens| tokens.result_id.as_ref() == Some(&params.previous_result_id))
+            .map(|tokens| tokens.data.clone());
+        let tokens = match self.compute_semantic_tokens(&url) {
+            Some(tokens) => tokens,
+            None => {
+                warn!("Document not found.");
+                return Ok(None);
+            }
+        };
+        // 前回の結果が手元に無ければ全体を返す。
+        match previous {
+            Some(previous) => Ok(Some(
+                tower_lsp::lsp_types::SemanticTokensDelta {
+                    result_id: tokens.result_id,
+                    edits: semantic_tokens::diff_tokens(&previous, &tokens.data),
+                }
+                .into(),
+            )),
+            None => Ok(Some(tokens.into())),
+        }
+    }
+
+    /// semantic tokens を計算し、新しい result id を付けて記憶しておく。
+    fn compute_semantic_tokens(
+        &mut self,
+        url: &tower_lsp::lsp_types::Url,
+    ) -> Option<tower_lsp::lsp_types::SemanticTokens> {
+        let document = self.document_cache.get(url)?;
+        let today = self.config.today();
+        self.semantic_tokens_id += 1;
+        let tokens = tower_lsp::lsp_types::SemanticTokens {
+            result_id: Some(self.semantic_tokens_id.to_string()),
+            data: document.get_semantic_tokens(today),
+        };
+        self.semantic_tokens.insert(url.clone(), tokens.clone());
+        Some(tokens)
+    }
+
+    async fn folding_range(
+        &mut self,
+        params: tower_lsp::lsp_types::FoldingRangeParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<Vec<tower_lsp::lsp_types::FoldingRange>>> {
+        let url = &params.text_document.uri;
+        if let Some(document) = self.document_cache.get(url) {
+            Ok(Some(document.get_folding_ranges(&self.config)))
+        } else {
+            warn!("Document not found.");
+            Ok(None)
+        }
+    }
+
+    async fn goto_definition(
+        &mut self,
+        params: tower_lsp::lsp_types::GotoDefinitionParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<tower_lsp::lsp_types::GotoDefinitionResponse>> {
+        let url = &params.text_document_position_params.text_document.uri;
+        let document = match self.document_cache.get(url) {
+            Some(document) => document,
+            None => {
+                warn!("Document not found.");
+                return Ok(None);
+            }
+        };
+        let pos = match params
+            .text_document_position_params
+            .position
+            .try_pos_into(document)
+        {
+            Some(pos) => pos,
+            None => return Ok(None),
+        };
+
+        let mut locations = document
+            .get_definitions(pos)
+            .into_iter()
+            .filter_map(|range| {
+                Some(tower_lsp::lsp_types::Location::new(
+                    url.clone(),
+                    range.try_pos_into(document)?,
+                ))
+            })
+            .collect::<Vec<_>>();
+        // カテゴリを持つヘッダがこのドキュメントに無ければ、ワークスペースから探す。
+        if locations.is_empty() {
+            if let Some((occurrence::Referent::Category(name), _)) = document.find_referent(pos) {
+                let found = self
+                    .workspace_documents()
+                    .into_iter()
+                    .find_map(|(url, document)| {
+                        let range = document.find_category_header(&name)?;
+                        let range = range.try_pos_into(document)?;
+                        Some(tower_lsp::lsp_types::Location::new(url, range))
+                    });
+                locations.extend(found);
+            }
+        }
+        Ok(Some(tower_lsp::lsp_types::GotoDefinitionResponse::Array(
+            locations,
+        )))
+    }
+
+    async fn references(
+        &mut self,
+        params: tower_lsp::lsp_types::ReferenceParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<Vec<tower_lsp::lsp_types::Location>>> {
+        let url = &params.text_document_position.text_document.uri;
+        let document = match self.document_cache.get(url) {
+            Some(document) => document,
+            None => {
+                warn!("Document not found.");
+                return Ok(None);
+            }
+        };
+        let referent = params
+            .text_document_position
+            .position
+            .try_pos_into(document)
+            .and_then(|pos| document.find_referent(pos));
+        let referent = match referent {
+            Some((referent, _)) => referent,
+            None => return Ok(None),
+        };
+        // カテゴリやタグには宣言にあたる箇所が無いので、`include_declaration` に関わらずすべて返す。
+        let locations = self
+            .workspace_documents()
+            .into_iter()
+            .flat_map(|(url, document)| document.get_references(&url, &referent))
+            .collect();
+        Ok(Some(locations))
+    }
+
+    async fn document_highlight(
+        &mut self,
+        params: tower_lsp::lsp_types::DocumentHighlightParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<Vec<tower_lsp::lsp_types::DocumentHighlight>>> {
+        let url = &params.text_document_position_params.text_document.uri;
+        if let Some(document) = self.document_cache.get(url) {
+            Ok(document.get_document_highlights(params.text_document_position_params.position))
+        } else {
+            warn!("Document not found.");
+            Ok(None)
+        }
+    }
+
+    async fn prepare_rename(
+        &mut self,
+        params: tower_lsp::lsp_types::TextDocumentPositionParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<tower_lsp::lsp_types::PrepareRenameResponse>> {
+        let url = &params.text_document.uri;
+        if let Some(document) = self.document_cache.get(url) {
+            Ok(document.get_prepare_rename(params.position))
+        } else {
+            warn!("Document not found.");
+            Ok(None)
+        }
+    }
+
+    async fn rename(
+        &mut self,
+        params: tower_lsp::lsp_types::RenameParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<tower_lsp::lsp_types::WorkspaceEdit>> {
+        let url = &params.text_document_position.text_document.uri;
+        let document = match self.document_cache.get(url) {
+            Some(document) => document,
+            None => {
+                warn!("Document not found.");
+                return Ok(None);
+            }
+        };
+        let referent = params
+            .text_document_position
+            .position
+            .try_pos_into(document)
+            .and_then(|pos| document.find_referent(pos));
+        let referent = match referent {
+            Some((referent, _)) => referent,
+            None => return Ok(None),
+        };
+        let new_name = rename::validate_new_name(&referent, &params.new_name)
+            .map_err(tower_lsp::jsonrpc::Error::invalid_params)?;
+
+        let changes = self
+            .workspace_documents()
+            .into_iter()
+            .map(|(url, document)| (url, document.get_rename_edits(&referent, &new_name)))
+            .filter(|(_, edits)| !edits.is_empty())
+            .collect();
+        Ok(Some(tower_lsp::lsp_types::WorkspaceEdit {
+            changes: Some(changes),
+            ..Default::default()
+        }))
+    }
+
+    async fn symbol(
+        &mut self,
+        params: tower_lsp::lsp_types::WorkspaceSymbolParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<Vec<tower_lsp::lsp_types::SymbolInformation>>> {
+        let symbols = self
+            .workspace_documents()
+            .into_iter()
+            .flat_map(|(url, document)| document.get_workspace_symbols(&url, &params.query))
+            .collect();
+        Ok(Some(symbols))
+    }
+
+    /// ワークスペース内のすべての `.todome` ファイル。
+    /// 開かれているものはキャッシュを、開かれていないものは索引を使う。
+    fn workspace_documents(&self) -> Vec<(tower_lsp::lsp_types::Url, &Document)> {
+        let opened = self
+            .document_cache
+            .iter()
+            .map(|(url, document)| (url.clone(), document));
+        let indexed = self
+            .workspace_index
+            .iter()
+            .filter(|(url, _)| self.document_cache.get(url).is_none())
+            .map(|(url, document)| (url.clone(), document));
+        opened.chain(indexed).collect()
+    }
+
+    /// `roots` 以下のファイルを読んで索引に加え、進捗を `$/progress` で知らせる。
+    /// ファイルを読む間はロックを持たないので、他のリクエストを待たせない。
+    async fn index_workspace(
+        inner: Arc<tokio::sync::Mutex<Inner>>,
+        roots: Vec<tower_lsp::lsp_types::Url>,
+    ) {
+        let paths = tokio::task::spawn_blocking(move || {
+            roots
+                .iter()
+                .filter_map(|root| root.to_file_path().ok())
+                .flat_map(|root| workspace::find_todome_files(&root))
+                .collect::<Vec<_>>()
+        })
+        .await
+        .unwrap_or_else(|e| {
+            error!("Failed to search workspace: {}", e);
+            vec![]
+        });
+        if paths.is_empty() {
+            return;
+        }
+        info!("Indexing {} files", paths.len());
+
+        let (client, supported) = {
+            let inner = inner.lock().await;
+            (inner.client.clone(), inner.work_done_progress)
+        };
+        let mut work_done =
+            progress::WorkDone::begin(client, supported, "Indexing workspace").await;
+        for (i, path) in paths.iter().enumerate() {
+            let url = match tower_lsp::lsp_types::Url::from_file_path(path) {
+                Ok(url) => url,
+                Err(_) => continue,
+            };
+            let loaded = {
+                let path = path.clone();
+                tokio::task::spawn_blocking(move || workspace::load_document(&path)).await
+            };
+            match loaded {
+                Ok(Ok(document)) => {
+                    // 読んでいる間にフォルダが外されたり、ファイルの変更の通知で新しい内容が
+                    // 索引に入ったりしていれば、読んだ内容は捨てる。
+                    let mut inner = inner.lock().await;
+                    if workspace::is_in_workspace(&inner.workspace_roots, &url)
+                        && inner.workspace_index.get(&url).is_none()
+                    {
+                        inner.workspace_index.insert(url, document);
+                    }
+                }
+                Ok(Err(e)) => warn!("Failed to read {}: {}", path.display(), e),
+                Err(e) => error!("Failed to load {}: {}", path.display(), e),
+            }
+            work_done
+                .report(
+                    format!("{}/{} files", i + 1, paths.len()),
+                    ((i + 1) * 100 / paths.len()) as u32,
+                )
+                .await;
+        }
+        work_done
+            .end(format!("Indexed {} files", paths.len()))
+            .await;
+    }
+
+    async fn document_symbol(
+        &mut self,
+        params: tower_lsp::lsp_types::DocumentSymbolParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<tower_lsp::lsp_types::DocumentSymbolResponse>> {
+        let url = &params.text_document.uri;
+        if let Some(document) = self.document_cache.get(url) {
+            Ok(Some(tower_lsp::lsp_types::DocumentSymbolResponse::Nested(
+                document.get_document_symbols(),
+            )))
+        } else {
+            warn!("Document not found.");
+            Ok(None)
+        }
+    }
+
+    async fn inlay_hint(
+        &mut self,
+        params: tower_lsp::lsp_types::InlayHintParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<Vec<tower_lsp::lsp_types::InlayHint>>> {
+        let url = &params.text_document.uri;
+        if let Some(document) = self.document_cache.get(url) {
+            let today = self.config.today();
+            Ok(Some(document.get_inlay_hints(
+                params.range,
+                today,
+                self.config.diagnostics.upcoming_deadline_days,
+            )))
+        } else {
+            warn!("Document not found.");
+            Ok(None)
+        }
+    }
+
+    async fn code_lens(
+        &mut self,
+        params: tower_lsp::lsp_types::CodeLensParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<Vec<tower_lsp::lsp_types::CodeLens>>> {
+        let url = &params.text_document.uri;
+        if let Some(document) = self.document_cache.get(url) {
+            let today = self.config.today();
+            Ok(Some(document.get_code_lenses(url, today)))
+        } else {
+            warn!("Document not found.");
+            Ok(None)
+        }
+    }
+
+    async fn execute_command(
+        &mut self,
+        params: tower_lsp::lsp_types::ExecuteCommandParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<serde_json::Value>> {
+        if params.command != code_lens::LIST_OPEN_CHILDREN {
+            return Err(tower_lsp::jsonrpc::Error::invalid_params(format!(
+                "Unknown command: {}",
+                params.command
+            )));
+        }
+        let (url, row) = match params.arguments.as_slice() {
+            [url, row] => (
+                serde_json::from_value::<tower_lsp::lsp_types::Url>(url.clone()).ok(),
+                row.as_u64(),
+            ),
+            _ => (None, None),
+        };
+        let (url, row) = match (url, row) {
+            (Some(url), Some(row)) => (url, row as usize),
+            _ => {
+                return Err(tower_lsp::jsonrpc::Error::invalid_params(
+                    "Expected a document URL and a line number.",
+                ))
+            }
+        };
+        let document = match self.document_cache.get(&url) {
+            Some(document) => document,
+            None => {
+                warn!("Document not found.");
+                return Ok(None);
+            }
+        };
+
+        let children = document.get_open_children(row);
+        let message = if children.is_empty() {
+            "No open tasks.".to_owned()
+        } else {
+            children
+                .iter()
+                .map(|(row, text)| format!("line {}: {}", row + 1, text))
+                .join("\n")
+        };
+        self.client
+            .show_message(tower_lsp::lsp_types::MessageType::INFO, message)
+            .await;
+        let children = children
+            .into_iter()
+            .map(|(row, text)| serde_json::json!({ "line": row, "text": text }))
+            .collect();
+        Ok(Some(serde_json::Value::Array(children)))
+    }
+
+    async fn hover(
+        &mut self,
+        params: tower_lsp::lsp_types::HoverParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<tower_lsp::lsp_types::Hover>> {
+        let url = &params.text_document_position_params.text_document.uri;
+        if let Some(document) = self.document_cache.get(url) {
+            Ok(document.get_hover(&params, self.config.today()))
+        } else {
+            warn!("Document not found.");
+            Ok(None)
+        }
+    }
+
+    async fn code_action(
+        &mut self,
+        params: tower_lsp::lsp_types::CodeActionParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<tower_lsp::lsp_types::CodeActionResponse>> {
+        let url = &params.text_document.uri;
+        if let Some(document) = self.document_cache.get(url) {
+            let mut actions = document.get_code_actions(&params, self.config.today());
+            if let Some(target) = self.archive_target(url) {
+                actions.extend(document.get_archive_action(url, &target));
+            }
+            Ok(Some(actions))
+        } else {
+            warn!("Document not found.");
+            Ok(None)
+        }
+    }
+
+    /// 設定に従って、完了したタスクのアーカイブ先を決める。
+    /// アーカイブ先のファイルが無く、クライアントがファイルを作れない場合は `None` を返す。
+    fn archive_target(&self, url: &tower_lsp::lsp_types::Url) -> Option<archive::ArchiveTarget> {
+        let archive_url = match self
+            .config
+            .archive_file
+            .as_ref()
+            .and_then(|name| url.join(name).ok())
+        {
+            Some(archive_url) if &archive_url != url => archive_url,
+            _ => return Some(archive::ArchiveTarget::Header),
+        };
+        // コードアクションのたびにファイルを読まないよう、開かれているものか索引にあるものを使う。
+        // どちらにも無ければまだ存在しないものとして扱う。
+        let text = self
+            .document_cache
+            .get(&archive_url)
+            .or_else(|| self.workspace_index.get(&archive_url))
+            .map(|document| document.text());
+        if text.is_none() && !self.create_files {
+            return None;
+        }
+        Some(archive::ArchiveTarget::file(archive_url, text))
+    }
+
+    async fn formatting(
+        &mut self,
+        params: tower_lsp::lsp_types::DocumentFormattingParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<Vec<tower_lsp::lsp_types::TextEdit>>> {
+        let url = &params.text_document.uri;
+        if let Some(document) = self.document_cache.get(url) {
+            let options = FormatOptions {
+                today: Some(self.config.today()),
+                ..FormatOptions::default()
+            };
+            let edits =
                 document
-                    .get_completion(&params)
+                    .get_formatting_edits(&options)
                     .map_err(|e| tower_lsp::jsonrpc::Error {
                         code: ErrorCode::InternalError,
-                        message: format!("{}", e),
+                        message: format!("{}", e).into(),
                         data: None,
                     })?;
-            debug!("completions: {:#?}", completions);
-            Ok(Some(CompletionResponse::Array(completions)))
+            Ok(Some(edits))
         } else {
             warn!("Document not found.");
             Ok(None)
         }
     }
+
+    async fn on_type_formatting(
+        &mut self,
+        params: tower_lsp::lsp_types::DocumentOnTypeFormattingParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<Vec<tower_lsp::lsp_types::TextEdit>>> {
+        let url = &params.text_document_position.text_document.uri;
+        if let Some(document) = self.document_cache.get(url) {
+            Ok(Some(document.get_on_type_formatting(&params, &self.config)))
+        } else {
+            warn!("Document not found.");
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::{lsp_types::*, LanguageServer as _, LspService};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_references_without_declaration() {
+        let (service, _socket) = LspService::new(LanguageServer::new);
+        let server = service.inner();
+        let uri = Url::parse("file:///todo.todome").unwrap();
+        server
+            .did_open(DidOpenTextDocumentParams {
+                text_document: TextDocumentItem {
+                    uri: uri.clone(),
+                    language_id: "todome".to_owned(),
+                    version: 1,
+                    text: "[work] # project\n\ttask\n\t[work] task\n".to_owned(),
+                },
+            })
+            .await;
+
+        let locations = server
+            .references(ReferenceParams {
+                text_document_position: TextDocumentPositionParams {
+                    text_document: TextDocumentIdentifier { uri },
+                    position: Position::new(0, 2),
+                },
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+                context: ReferenceContext {
+                    include_declaration: false,
+                },
+            })
+            .await
+            .unwrap()
+            .unwrap();
+        // ヘッダから継承している 2 行目のタスクの分も、ヘッダの行として含まれる。
+        let lines = locations
+            .iter()
+            .map(|location| (location.range.start.line, location.range.end.character))
+            .collect::<Vec<_>>();
+        assert_eq!(lines, vec![(0, 16), (2, 7)]);
+    }
 }
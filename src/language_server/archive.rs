@@ -0,0 +1,416 @@
+//! 完了したタスクをアーカイブするコードアクション。
+
+use std::collections::HashMap;
+
+use itertools::Itertools;
+use tower_lsp::lsp_types::{
+    CodeAction, CodeActionKind, CodeActionOrCommand, CreateFile, CreateFileOptions,
+    DocumentChangeOperation, DocumentChanges, OneOf, OptionalVersionedTextDocumentIdentifier,
+    Position, Range, ResourceOp, TextDocumentEdit, TextEdit, Url, WorkspaceEdit,
+};
+use tree_sitter_todome::syntax::ast::{AstNode, Meta};
+
+use crate::{
+    structure::{position::PosInto, syntax::Document},
+    subcmd::format::{format_dates, status_symbol},
+};
+
+/// 同じドキュメント内でアーカイブ先とする行。
+/// 属性を持たないコメントだけの行なので、その下に移したタスクにカテゴリなどが加わらない。
+const ARCHIVE_HEADER: &str = "# Archive";
+
+/// アーカイブしたタスクの移動先。
+#[derive(Debug, Clone)]
+pub enum ArchiveTarget {
+    /// ドキュメント末尾の `# Archive` 行の下。
+    Header,
+    /// 別のファイルの末尾。
+    File {
+        url: Url,
+        /// ファイルの末尾の位置。
+        end: Position,
+        /// ファイルが既に存在するかどうか。存在しなければ `CreateFile` で作る。
+        exists: bool,
+        /// 末尾が改行で終わっていないかどうか。
+        needs_newline: bool,
+    },
+}
+
+impl ArchiveTarget {
+    /// 別ファイルのテキストから移動先を作る。ファイルが無ければ `text` は `None`。
+    pub fn file(url: Url, text: Option<&str>) -> ArchiveTarget {
+        let text = text.unwrap_or_default();
+        let last_line = text.rsplit('\n').next().unwrap_or_default();
+        ArchiveTarget::File {
+            url,
+            end: Position::new(
+                text.matches('\n').count() as u32,
+                last_line.encode_utf16().count() as u32,
+            ),
+            exists: !text.is_empty(),
+            needs_newline: !text.is_empty() && !text.ends_with('\n'),
+        }
+    }
+}
+
+impl Document {
+    /// 完了またはキャンセル済みのタスクだけからなる部分木を、まとめて移動先に移す。
+    pub fn get_archive_action(
+        &self,
+        url: &Url,
+        target: &ArchiveTarget,
+    ) -> Option<CodeActionOrCommand> {
+        let outline = self.outline();
+        let archive_header = outline.roots().into_iter().find(|&idx| {
+            outline.node(idx).is_memo() && self.line(outline.node(idx).row) == Some(ARCHIVE_HEADER)
+        });
+
+        let subtrees = self.finished_subtrees(archive_header);
+        if subtrees.is_empty() {
+            return None;
+        }
+
+        let indent = match target {
+            ArchiveTarget::Header => "\t",
+            ArchiveTarget::File { .. } => "",
+        };
+        let moved = subtrees
+            .iter()
+            .flat_map(|&idx| self.archived_lines(idx, indent))
+            .map(|line| format!("{}\n", line))
+            .join("");
+
+        let mut edits = subtrees
+            .iter()
+            .filter_map(|&idx| {
+                let node = outline.node(idx);
+                let start = self.lines()[node.row];
+                let end = self
+                    .lines()
+                    .get(outline.last_row(idx) + 1)
+                    .copied()
+                    .unwrap_or(self.text().len());
+                Some(TextEdit {
+                    range: (start, end).try_pos_into(self)?,
+                    new_text: String::new(),
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let edit = match target {
+            ArchiveTarget::Header => {
+                let (pos, new_text) = match archive_header {
+                    Some(header) => {
+                        let pos = self
+                            .lines()
+                            .get(outline.last_row(header) + 1)
+                            .copied()
+                            .unwrap_or(self.text().len());
+                        (pos, moved)
+                    }
+                    None => {
+                        let newline = if self.text().is_empty() || self.text().ends_with('\n') {
+                            ""
+                        } else {
+                            "\n"
+                        };
+                        let pos = self.text().len();
+                        (pos, format!("{}\n{}\n{}", newline, ARCHIVE_HEADER, moved))
+                    }
+                };
+                edits.push(TextEdit {
+                    range: (pos, pos).try_pos_into(self)?,
+                    new_text,
+                });
+                WorkspaceEdit {
+                    changes: Some(HashMap::from([(url.clone(), edits)])),
+                    ..Default::default()
+                }
+            }
+            ArchiveTarget::File {
+                url: archive_url,
+                end,
+                exists,
+                needs_newline,
+            } => {
+                let newline = if *needs_newline { "\n" } else { "" };
+                let archive_edits = vec![TextEdit {
+                    range: Range::new(*end, *end),
+                    new_text: format!("{}{}", newline, moved),
+                }];
+                if *exists {
+                    // ファイルを作らなくてよければ、どのクライアントでも使える `changes` で返す。
+                    WorkspaceEdit {
+                        changes: Some(HashMap::from([
+                            (archive_url.clone(), archive_edits),
+                            (url.clone(), edits),
+                        ])),
+                        ..Default::default()
+                    }
+                } else {
+                    let create = ResourceOp::Create(CreateFile {
+                        uri: archive_url.clone(),
+                        options: Some(CreateFileOptions {
+                            overwrite: Some(false),
+                            ignore_if_exists: Some(true),
+                        }),
+                        annotation_id: None,
+                    });
+                    let operations = vec![
+                        DocumentChangeOperation::Op(create),
+                        text_document_edit(archive_url, archive_edits),
+                        text_document_edit(url, edits),
+                    ];
+                    WorkspaceEdit {
+                        document_changes: Some(DocumentChanges::Operations(operations)),
+                        ..Default::default()
+                    }
+                }
+            }
+        };
+
+        Some(CodeActionOrCommand::CodeAction(CodeAction {
+            title: "Archive completed tasks".to_owned(),
+            kind: Some(CodeActionKind::SOURCE),
+            edit: Some(edit),
+            ..Default::default()
+        }))
+    }
+
+    /// 完了またはキャンセル済みのタスクだけからなる部分木の根を文書順に返す。
+    /// 既にアーカイブ用のヘッダの下にあるものは除く。
+    fn finished_subtrees(&self, archive_header: Option<usize>) -> Vec<usize> {
+        let outline = self.outline();
+        let mut subtrees: Vec<usize> = vec![];
+        for idx in 0..outline.nodes().len() {
+            let node = outline.node(idx);
+            if !node.is_task() || !outline.attributes(idx).is_finished() {
+                continue;
+            }
+            let ancestors = outline.ancestors(idx);
+            if archive_header.is_some_and(|header| ancestors.contains(&header))
+                || ancestors.iter().any(|parent| subtrees.contains(parent))
+            {
+                continue;
+            }
+            let finished = outline.descendants(idx).into_iter().all(|child| {
+                !outline.node(child).is_task() || outline.attributes(child).is_finished()
+            });
+            if finished {
+                subtrees.push(idx);
+            }
+        }
+        subtrees
+    }
+
+    /// 部分木を移動先に書く行。インデントを `indent` に付け替え、
+    /// 祖先から継承していた属性を部分木の根に明示する。
+    fn archived_lines(&self, idx: usize, indent: &str) -> Vec<String> {
+        let outline = self.outline();
+        let node = outline.node(idx);
+        (node.row..=outline.last_row(idx))
+            .map(|row| {
+                let line = self.line(row).unwrap_or_default();
+                let line = if row == node.row {
+                    self.with_explicit_attributes(idx)
+                } else {
+                    let node_indent = "\t".repeat(node.indent);
+                    line.strip_prefix(&node_indent).unwrap_or(line).to_owned()
+                };
+                if line.is_empty() {
+                    line
+                } else {
+                    format!("{}{}", indent, line)
+                }
+            })
+            .collect()
+    }
+
+    /// インデントを除いた節の行に、祖先から継承していた status と meta を書き加えたもの。
+    fn with_explicit_attributes(&self, idx: usize) -> String {
+        let outline = self.outline();
+        let node = outline.node(idx);
+        let attrs = outline.attributes(idx);
+        let start_of_line = self.lines()[node.row];
+        let offset = |pos: usize| pos - node.range.0;
+        let mut line =
+            self.line(node.row).unwrap_or_default()[(node.range.0 - start_of_line)..].to_owned();
+
+        let mut metas = vec![];
+        if let Some(priority) = attrs.priority.as_ref().filter(|p| p.source != idx) {
+            metas.push(format!("({})", priority.value));
+        }
+        if attrs.dates.iter().flatten().any(|date| date.source != idx) {
+            // 自身に書かれた日付は、継承した日付とまとめて書き直す。
+            for meta in node.meta().into_iter().rev() {
+                if let Meta::Date(date) = meta {
+                    let (start, end) = date.syntax().range();
+                    let (start, end) = (offset(start), offset(end));
+                    let end = end + usize::from(line[end..].starts_with(' '));
+                    line.replace_range(start..end, "");
+                }
+            }
+            let dates = [0, 1, 2].map(|i| attrs.dates[i].as_ref().map(|date| date.value));
+            metas.extend(format_dates(dates));
+        }
+        for category in attrs.categories.iter().filter(|c| c.source != idx) {
+            metas.push(format!("[{}]", category.value));
+        }
+
+        let (status, rest) = match node.status() {
+            Some(status) => {
+                let end = offset(status.syntax().range().1);
+                (line[..end].to_owned(), line[end..].trim_start().to_owned())
+            }
+            None => {
+                let status = attrs
+                    .status
+                    .as_ref()
+                    .map(|status| status_symbol(status.value).to_owned())
+                    .unwrap_or_default();
+                (status, line)
+            }
+        };
+        [status, metas.join(" "), rest]
+            .into_iter()
+            .filter(|s| !s.is_empty())
+            .join(" ")
+    }
+}
+
+fn text_document_edit(url: &Url, edits: Vec<TextEdit>) -> DocumentChangeOperation {
+    DocumentChangeOperation::Edit(TextDocumentEdit {
+        text_document: OptionalVersionedTextDocumentIdentifier {
+            uri: url.clone(),
+            version: None,
+        },
+        edits: edits.into_iter().map(OneOf::Left).collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_archive_to_header() {
+        let document =
+            Document::parse("(A) [work]\n\t- task one\n\t\t# memo\n\t+ task two\n".to_owned())
+                .unwrap();
+        let url = Url::parse("file:///todo.todome").unwrap();
+        let action = match document.get_archive_action(&url, &ArchiveTarget::Header) {
+            Some(CodeActionOrCommand::CodeAction(action)) => action,
+            _ => panic!("no archive action"),
+        };
+        let edits = action.edit.unwrap().changes.unwrap().remove(&url).unwrap();
+        let edits = edits
+            .into_iter()
+            .map(|edit| (edit.range.start.line, edit.range.end.line, edit.new_text))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            edits,
+            vec![
+                (1, 3, String::new()),
+                (
+                    4,
+                    4,
+                    "\n# Archive\n\t- (A) [work] task one\n\t\t# memo\n".to_owned()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_archive_to_file() {
+        let document =
+            Document::parse("(A) [work]\n\t- task one\n\t\t# memo\n\t+ task two\n".to_owned())
+                .unwrap();
+        let url = Url::parse("file:///todo.todome").unwrap();
+        let archive_url = Url::parse("file:///archive.todome").unwrap();
+        let moved = "- (A) [work] task one\n\t# memo\n";
+        let archive_edit = |target: &ArchiveTarget| match document.get_archive_action(&url, target)
+        {
+            Some(CodeActionOrCommand::CodeAction(action)) => action.edit.unwrap(),
+            _ => panic!("no archive action"),
+        };
+        let summary = |edits: Vec<TextEdit>| {
+            edits
+                .into_iter()
+                .map(|edit| (edit.range.start, edit.range.end, edit.new_text))
+                .collect::<Vec<_>>()
+        };
+        let removed = vec![(Position::new(1, 0), Position::new(3, 0), String::new())];
+
+        // ファイルが無ければ作ってから、先頭に書き込む
+        let target = ArchiveTarget::file(archive_url.clone(), None);
+        assert!(matches!(
+            target,
+            ArchiveTarget::File {
+                exists: false,
+                needs_newline: false,
+                ..
+            }
+        ));
+        let operations = match archive_edit(&target).document_changes {
+            Some(DocumentChanges::Operations(operations)) => operations,
+            _ => panic!("no document changes"),
+        };
+        assert_eq!(operations.len(), 3);
+        match &operations[0] {
+            DocumentChangeOperation::Op(ResourceOp::Create(create)) => {
+                assert_eq!(create.uri, archive_url);
+                assert_eq!(
+                    create.options.as_ref().unwrap().ignore_if_exists,
+                    Some(true)
+                );
+            }
+            _ => panic!("the archive file is not created first"),
+        }
+        let text_edits = operations[1..]
+            .iter()
+            .map(|operation| match operation {
+                DocumentChangeOperation::Edit(edit) => (
+                    edit.text_document.uri.clone(),
+                    summary(
+                        edit.edits
+                            .iter()
+                            .map(|edit| match edit {
+                                OneOf::Left(edit) => edit.clone(),
+                                OneOf::Right(edit) => edit.text_edit.clone(),
+                            })
+                            .collect(),
+                    ),
+                ),
+                _ => panic!("unexpected resource operation"),
+            })
+            .collect::<Vec<_>>();
+        let start = Position::new(0, 0);
+        assert_eq!(
+            text_edits,
+            vec![
+                (archive_url.clone(), vec![(start, start, moved.to_owned())]),
+                (url.clone(), removed.clone()),
+            ]
+        );
+
+        // 既にあるファイルには、末尾に改行を補ってから書き込む
+        let target = ArchiveTarget::file(archive_url.clone(), Some("old task"));
+        assert!(matches!(
+            target,
+            ArchiveTarget::File {
+                exists: true,
+                needs_newline: true,
+                ..
+            }
+        ));
+        let edit = archive_edit(&target);
+        assert!(edit.document_changes.is_none());
+        let mut changes = edit.changes.unwrap();
+        let end = Position::new(0, 8);
+        assert_eq!(
+            summary(changes.remove(&archive_url).unwrap()),
+            vec![(end, end, format!("\n{}", moved))]
+        );
+        assert_eq!(summary(changes.remove(&url).unwrap()), removed);
+    }
+}
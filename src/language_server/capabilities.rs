@@ -1,41 +1,101 @@
 use tower_lsp::lsp_types::{
-    ClientCapabilities, CompletionOptions, ServerCapabilities, TextDocumentSyncCapability,
-    TextDocumentSyncKind,
+    ClientCapabilities, CodeActionKind, CodeActionOptions, CodeActionProviderCapability,
+    CodeLensOptions, CompletionOptions, DeclarationCapability, DiagnosticOptions,
+    DiagnosticServerCapabilities, DocumentOnTypeFormattingOptions, ExecuteCommandOptions,
+    FoldingRangeProviderCapability, HoverProviderCapability, OneOf, RenameOptions,
+    SemanticTokensFullOptions, SemanticTokensOptions, SemanticTokensServerCapabilities,
+    ServerCapabilities, TextDocumentSyncCapability, TextDocumentSyncKind, WorkDoneProgressOptions,
+    WorkspaceFoldersServerCapabilities, WorkspaceServerCapabilities,
 };
 
-pub fn server_capabilities(_client_capabilities: &ClientCapabilities) -> ServerCapabilities {
+use super::{code_lens, semantic_tokens};
+
+pub fn server_capabilities(client_capabilities: &ClientCapabilities) -> ServerCapabilities {
     ServerCapabilities {
-        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::Full)),
+        position_encoding: None,
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(
+            TextDocumentSyncKind::INCREMENTAL,
+        )),
         selection_range_provider: None,
-        hover_provider: None,
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
         completion_provider: Some(CompletionOptions {
             trigger_characters: Some(vec!["[".to_owned(), "@".to_owned(), "(".to_owned()]),
             ..CompletionOptions::default()
         }),
         signature_help_provider: None,
-        definition_provider: None,
+        definition_provider: Some(OneOf::Left(true)),
         type_definition_provider: None,
         implementation_provider: None,
-        references_provider: None,
-        document_highlight_provider: None,
-        document_symbol_provider: None,
-        workspace_symbol_provider: None,
-        code_action_provider: None,
-        code_lens_provider: None,
-        document_formatting_provider: None,
+        references_provider: Some(OneOf::Left(true)),
+        document_highlight_provider: Some(OneOf::Left(true)),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        workspace_symbol_provider: Some(OneOf::Left(true)),
+        code_action_provider: Some(CodeActionProviderCapability::Options(CodeActionOptions {
+            code_action_kinds: Some(vec![
+                CodeActionKind::REFACTOR_REWRITE,
+                CodeActionKind::SOURCE,
+            ]),
+            work_done_progress_options: WorkDoneProgressOptions::default(),
+            resolve_provider: None,
+        })),
+        code_lens_provider: Some(CodeLensOptions {
+            resolve_provider: Some(false),
+        }),
+        document_formatting_provider: Some(OneOf::Left(true)),
         document_range_formatting_provider: None,
-        document_on_type_formatting_provider: None,
-        rename_provider: None,
+        document_on_type_formatting_provider: Some(DocumentOnTypeFormattingOptions {
+            first_trigger_character: "\n".to_owned(),
+            more_trigger_character: Some(vec![" ".to_owned()]),
+        }),
+        rename_provider: Some(OneOf::Right(RenameOptions {
+            prepare_provider: Some(true),
+            work_done_progress_options: WorkDoneProgressOptions::default(),
+        })),
         document_link_provider: None,
         color_provider: None,
-        folding_range_provider: None,
-        declaration_provider: None,
-        execute_command_provider: None,
-        workspace: None,
+        folding_range_provider: Some(FoldingRangeProviderCapability::Simple(true)),
+        declaration_provider: Some(DeclarationCapability::Simple(true)),
+        execute_command_provider: Some(ExecuteCommandOptions {
+            commands: vec![code_lens::LIST_OPEN_CHILDREN.to_owned()],
+            work_done_progress_options: WorkDoneProgressOptions::default(),
+        }),
+        workspace: Some(WorkspaceServerCapabilities {
+            workspace_folders: Some(WorkspaceFoldersServerCapabilities {
+                supported: Some(true),
+                change_notifications: Some(OneOf::Left(true)),
+            }),
+            file_operations: None,
+        }),
         call_hierarchy_provider: None,
-        semantic_tokens_provider: None,
+        semantic_tokens_provider: Some(SemanticTokensServerCapabilities::SemanticTokensOptions(
+            SemanticTokensOptions {
+                work_done_progress_options: WorkDoneProgressOptions::default(),
+                legend: semantic_tokens::legend(),
+                range: None,
+                full: Some(SemanticTokensFullOptions::Delta { delta: Some(true) }),
+            },
+        )),
         moniker_provider: None,
         linked_editing_range_provider: None,
+        inline_value_provider: None,
+        inlay_hint_provider: Some(OneOf::Left(true)),
+        diagnostic_provider: supports_pull_diagnostics(client_capabilities).then(|| {
+            DiagnosticServerCapabilities::Options(DiagnosticOptions {
+                identifier: Some("todome".to_owned()),
+                inter_file_dependencies: false,
+                workspace_diagnostics: true,
+                work_done_progress_options: WorkDoneProgressOptions::default(),
+            })
+        }),
         experimental: None,
     }
 }
+
+/// クライアントが pull 型の diagnostics に対応しているかどうか。
+/// 対応していれば diagnostics は送らず、問い合わせに答える。
+pub fn supports_pull_diagnostics(client_capabilities: &ClientCapabilities) -> bool {
+    client_capabilities
+        .text_document
+        .as_ref()
+        .is_some_and(|text_document| text_document.diagnostic.is_some())
+}
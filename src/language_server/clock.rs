@@ -0,0 +1,96 @@
+//! 日付が変わったことの検知。
+//!
+//! 日付に依存する diagnostics は、開いたまま日をまたぐと古くなる。
+//! 日付が変わったときと、スリープからの復帰などで時計が飛んだときに知らせる。
+
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime};
+
+/// 日付の変わり目を待つ間も、この間隔で時計を確かめる。
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// 実時間と経過時間のずれがこれより大きければ、時計が飛んだとみなす。
+const JUMP_TOLERANCE: Duration = Duration::from_secs(30);
+
+/// 時計の監視。
+pub struct Clock {
+    date: NaiveDate,
+    wall: DateTime<Local>,
+    instant: Instant,
+}
+
+impl Clock {
+    /// 現在の時刻から監視を始める。
+    pub fn start() -> Clock {
+        let wall = Local::now();
+        Clock {
+            date: wall.naive_local().date(),
+            wall,
+            instant: Instant::now(),
+        }
+    }
+
+    /// 日付が変わるか時計が飛ぶまで待つ。
+    pub async fn changed(&mut self) {
+        loop {
+            tokio::time::sleep(until_next_check(Local::now().naive_local())).await;
+            let wall = Local::now();
+            let instant = Instant::now();
+            let jumped = jumped(wall - self.wall, instant - self.instant);
+            let date_changed = wall.naive_local().date() != self.date;
+            self.date = wall.naive_local().date();
+            self.wall = wall;
+            self.instant = instant;
+            if jumped || date_changed {
+                return;
+            }
+        }
+    }
+}
+
+/// 次に時計を確かめるまでの時間。次の日付の変わり目か [`CHECK_INTERVAL`] 後の早い方。
+fn until_next_check(now: NaiveDateTime) -> Duration {
+    let midnight = now
+        .date()
+        .succ_opt()
+        .and_then(|tomorrow| tomorrow.and_hms_opt(0, 0, 0))
+        .map(|midnight| midnight - now);
+    match midnight.and_then(|midnight| midnight.to_std().ok()) {
+        // 変わり目ちょうどに起きても日付が変わっていないことがあるので、少し遅らせる。
+        Some(midnight) => (midnight + Duration::from_secs(1)).min(CHECK_INTERVAL),
+        None => CHECK_INTERVAL,
+    }
+}
+
+/// 実時間の進み `wall` と経過時間 `elapsed` が大きく食い違っていれば true。
+/// スリープ中は経過時間が進まないので、復帰すると実時間の方が大きく進んで見える。
+fn jumped(wall: chrono::Duration, elapsed: Duration) -> bool {
+    match (wall.to_std(), elapsed.checked_add(JUMP_TOLERANCE)) {
+        (Ok(wall), Some(upper)) => wall > upper || wall + JUMP_TOLERANCE < elapsed,
+        // 実時間が戻った。
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_until_next_check() {
+        let date = NaiveDate::from_ymd_opt(2021, 11, 10).unwrap();
+        let evening = date.and_hms_opt(23, 59, 30).unwrap();
+        assert_eq!(until_next_check(evening), Duration::from_secs(31));
+        let noon = date.and_hms_opt(12, 0, 0).unwrap();
+        assert_eq!(until_next_check(noon), CHECK_INTERVAL);
+    }
+
+    #[test]
+    fn test_jumped() {
+        let minute = Duration::from_secs(60);
+        assert!(!jumped(chrono::Duration::seconds(61), minute));
+        assert!(jumped(chrono::Duration::hours(8), minute));
+        assert!(jumped(chrono::Duration::hours(-1), minute));
+    }
+}
@@ -0,0 +1,293 @@
+use std::collections::HashMap;
+
+use chrono::{Duration, NaiveDate};
+use tower_lsp::lsp_types::{
+    CodeAction, CodeActionKind, CodeActionOrCommand, CodeActionParams, Range, TextEdit, Url,
+    WorkspaceEdit,
+};
+use tree_sitter_todome::syntax::ast::{AstNode, Meta, StatusKind};
+
+use crate::{
+    structure::{position::PosInto, syntax::Document},
+    subcmd::format::{format_dates, shorthand::find_shorthands, status_symbol},
+};
+
+impl Document {
+    pub fn get_code_actions(
+        &self,
+        params: &CodeActionParams,
+        today: NaiveDate,
+    ) -> Vec<CodeActionOrCommand> {
+        let url = &params.text_document.uri;
+        let mut actions = vec![];
+        if let Some(idx) = self.outline().find_by_row(params.range.start.line as usize) {
+            if self.outline().node(idx).is_task() {
+                actions.extend(self.get_status_actions(url, idx));
+                actions.extend(self.get_priority_actions(url, idx));
+                actions.extend(self.get_postpone_actions(url, idx));
+            }
+        }
+        actions.extend(self.get_shorthand_actions(url, params.range, today));
+        actions
+    }
+
+    /// status を順に送る（todo → doing → done）操作、キャンセルする操作、子孫をまとめて完了にする操作。
+    fn get_status_actions(&self, url: &Url, idx: usize) -> Vec<CodeActionOrCommand> {
+        let outline = self.outline();
+        let current = outline.attributes(idx).status_kind();
+        let next = match current {
+            StatusKind::Todo => StatusKind::Doing,
+            StatusKind::Doing => StatusKind::Done,
+            _ => StatusKind::Todo,
+        };
+
+        let mut actions = vec![];
+        if let Some(edit) = self.status_edit(idx, next) {
+            let title = match next {
+                StatusKind::Doing => "Mark as doing",
+                StatusKind::Done => "Mark as done",
+                _ => "Mark as todo",
+            };
+            actions.push(make_action(url, title, vec![edit]));
+        }
+        if current != StatusKind::Cancel {
+            if let Some(edit) = self.status_edit(idx, StatusKind::Cancel) {
+                actions.push(make_action(url, "Mark as cancelled", vec![edit]));
+            }
+        }
+
+        let descendants = outline.descendants(idx);
+        if descendants
+            .iter()
+            .any(|&child| outline.node(child).is_task())
+        {
+            // 子孫のうち status が明示されていて未完了のものだけを書き換える。
+            // status の無い子孫は親の status を引き継ぐので触らない。
+            let edits = std::iter::once(idx)
+                .filter(|_| current != StatusKind::Done)
+                .chain(descendants.into_iter().filter(|&child| {
+                    let node = outline.node(child);
+                    node.is_task()
+                        && node.status().is_some_and(|status| {
+                            !matches!(status.kind(), StatusKind::Done | StatusKind::Cancel)
+                        })
+                }))
+                .filter_map(|idx| self.status_edit(idx, StatusKind::Done))
+                .collect::<Vec<_>>();
+            if !edits.is_empty() {
+                actions.push(make_action(url, "Mark subtree as done", edits));
+            }
+        }
+        actions
+    }
+
+    /// 節の status を `kind` に書き換える編集。status が書かれていなければ行頭に挿入する。
+    fn status_edit(&self, idx: usize, kind: StatusKind) -> Option<TextEdit> {
+        let node = self.outline().node(idx);
+        let symbol = status_symbol(kind);
+        match node.status() {
+            Some(status) if status.kind() == kind => None,
+            Some(status) => Some(TextEdit {
+                range: status.syntax().range().try_pos_into(self)?,
+                new_text: symbol.to_owned(),
+            }),
+            None => Some(TextEdit {
+                range: (node.range.0, node.range.0).try_pos_into(self)?,
+                new_text: format!("{} ", symbol),
+            }),
+        }
+    }
+
+    /// 優先度を A〜C に設定する、あるいは取り除く操作。
+    fn get_priority_actions(&self, url: &Url, idx: usize) -> Vec<CodeActionOrCommand> {
+        let node = self.outline().node(idx);
+        let priority = node.meta().into_iter().find_map(|meta| match meta {
+            Meta::Priority(priority) => Some(priority),
+            _ => None,
+        });
+
+        let mut actions = vec![];
+        for value in ["A", "B", "C"] {
+            let new_text = format!("({})", value);
+            let edit = match &priority {
+                Some(priority) if priority.value() == value => continue,
+                Some(priority) => priority
+                    .syntax()
+                    .range()
+                    .try_pos_into(self)
+                    .map(|range| TextEdit { range, new_text }),
+                None => self.insert_meta_edit(idx, &new_text),
+            };
+            let Some(edit) = edit else {
+                continue;
+            };
+            let title = format!("Set priority to {}", value);
+            actions.push(make_action(url, &title, vec![edit]));
+        }
+
+        if let Some(priority) = priority {
+            // 後ろに続く空白も一緒に消す。
+            let (start, end) = priority.syntax().range();
+            let end = end + usize::from(self.text()[end..].starts_with(' '));
+            if let Some(range) = (start, end).try_pos_into(self) {
+                let edit = TextEdit {
+                    range,
+                    new_text: String::new(),
+                };
+                actions.push(make_action(url, "Clear priority", vec![edit]));
+            }
+        }
+        actions
+    }
+
+    /// meta を status の直後（status が無ければ行頭）に挿入する編集。
+    fn insert_meta_edit(&self, idx: usize, meta: &str) -> Option<TextEdit> {
+        let node = self.outline().node(idx);
+        match node.status() {
+            Some(status) => {
+                let end = status.syntax().range().1;
+                Some(TextEdit {
+                    range: (end, end).try_pos_into(self)?,
+                    new_text: format!(" {}", meta),
+                })
+            }
+            None => Some(TextEdit {
+                range: (node.range.0, node.range.0).try_pos_into(self)?,
+                new_text: format!("{} ", meta),
+            }),
+        }
+    }
+
+    /// 目標日と締切日を 1 日または 1 週間後ろにずらす操作。開始日は変えない。
+    fn get_postpone_actions(&self, url: &Url, idx: usize) -> Vec<CodeActionOrCommand> {
+        let node = self.outline().node(idx);
+        let date = node.meta().into_iter().find_map(|meta| match meta {
+            Meta::Date(date) => Some(date),
+            _ => None,
+        });
+        let date = match date {
+            Some(date) if date.target().is_some() || date.deadline().is_some() => date,
+            _ => return vec![],
+        };
+        let range = match date.syntax().range().try_pos_into(self) {
+            Some(range) => range,
+            None => return vec![],
+        };
+
+        [("Postpone by 1 day", 1), ("Postpone by 1 week", 7)]
+            .into_iter()
+            .filter_map(|(title, days)| {
+                let shift = |d: Option<NaiveDate>| d.map(|d| d + Duration::days(days));
+                let new_text =
+                    format_dates([date.start(), shift(date.target()), shift(date.deadline())])?;
+                Some(make_action(url, title, vec![TextEdit { range, new_text }]))
+            })
+            .collect()
+    }
+
+    /// 選択範囲の行に含まれる `(tomorrow)` などの日付の略記を ISO 形式に展開する。
+    fn get_shorthand_actions(
+        &self,
+        url: &Url,
+        range: Range,
+        today: NaiveDate,
+    ) -> Vec<CodeActionOrCommand> {
+        let edits: Vec<TextEdit> = (range.start.line..=range.end.line)
+            .flat_map(|row| {
+                let row = row as usize;
+                let Some(line) = self.line(row) else {
+                    return vec![];
+                };
+                let start_of_line = self.lines()[row];
+                find_shorthands(line, today)
+                    .into_iter()
+                    .filter_map(|(range, new_text)| {
+                        let range = (start_of_line + range.start, start_of_line + range.end)
+                            .try_pos_into(self)?;
+                        Some(TextEdit { range, new_text })
+                    })
+                    .collect()
+            })
+            .collect();
+        if edits.is_empty() {
+            return vec![];
+        }
+
+        vec![make_action(url, "Expand date shorthands", edits)]
+    }
+}
+
+fn make_action(url: &Url, title: &str, edits: Vec<TextEdit>) -> CodeActionOrCommand {
+    CodeActionOrCommand::CodeAction(CodeAction {
+        title: title.to_owned(),
+        kind: Some(CodeActionKind::REFACTOR_REWRITE),
+        edit: Some(WorkspaceEdit {
+            changes: Some(HashMap::from([(url.clone(), edits)])),
+            ..Default::default()
+        }),
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::{Position, TextDocumentIdentifier};
+
+    use super::*;
+
+    #[test]
+    fn test_task_actions() {
+        let document = Document::parse(
+            "* (B) (2021-11-10) task
+	+ sub
+	- sub done
+"
+            .to_owned(),
+        )
+        .unwrap();
+        let url = Url::parse("file:///todo.todome").unwrap();
+        let params = CodeActionParams {
+            text_document: TextDocumentIdentifier { uri: url.clone() },
+            range: Range::new(Position::new(0, 0), Position::new(0, 0)),
+            context: Default::default(),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let actions = document
+            .get_code_actions(&params, NaiveDate::from_ymd_opt(2021, 11, 10).unwrap())
+            .into_iter()
+            .filter_map(|action| match action {
+                CodeActionOrCommand::CodeAction(action) => Some(action),
+                _ => None,
+            })
+            .map(|action| {
+                let edits = action.edit.unwrap().changes.unwrap().remove(&url).unwrap();
+                let edits = edits
+                    .into_iter()
+                    .map(|edit| (edit.range.start.line, edit.new_text))
+                    .collect::<Vec<_>>();
+                (action.title, edits)
+            })
+            .collect::<Vec<_>>();
+
+        let find = |title: &str| {
+            actions
+                .iter()
+                .find(|(t, _)| t == title)
+                .map(|(_, edits)| edits.clone())
+        };
+        assert_eq!(find("Mark as done"), Some(vec![(0, "-".to_owned())]));
+        assert_eq!(find("Mark as cancelled"), Some(vec![(0, "=".to_owned())]));
+        assert_eq!(
+            find("Mark subtree as done"),
+            Some(vec![(0, "-".to_owned()), (1, "-".to_owned())])
+        );
+        assert_eq!(find("Set priority to A"), Some(vec![(0, "(A)".to_owned())]));
+        assert_eq!(find("Set priority to B"), None);
+        assert_eq!(find("Clear priority"), Some(vec![(0, String::new())]));
+        assert_eq!(
+            find("Postpone by 1 week"),
+            Some(vec![(0, "(2021-11-17)".to_owned())])
+        );
+    }
+}
@@ -0,0 +1,145 @@
+use chrono::NaiveDate;
+use serde_json::json;
+use tower_lsp::lsp_types::{CodeLens, Command, Url};
+use tree_sitter_todome::syntax::ast::StatusKind;
+
+use crate::structure::{position::PosInto, syntax::Document};
+
+/// 子孫のうち未完了のタスクを一覧するコマンド。引数はドキュメントの URL と行番号。
+pub const LIST_OPEN_CHILDREN: &str = "todome.listOpenChildren";
+
+/// ヘッダやタスクの子孫の進捗。
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Progress {
+    /// キャンセルされたものを除いたタスクの数。
+    total: usize,
+    done: usize,
+    /// 締切を過ぎた未完了のタスクの数。
+    overdue: usize,
+    /// 未完了のタスクのうち、今日以降で最も近い締切。
+    next_due: Option<NaiveDate>,
+}
+
+impl std::fmt::Display for Progress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{} done", self.done, self.total)?;
+        if self.overdue > 0 {
+            write!(f, " · {} overdue", self.overdue)?;
+        }
+        if let Some(next_due) = self.next_due {
+            write!(f, " · next due {}", next_due.format("%Y-%m-%d"))?;
+        }
+        Ok(())
+    }
+}
+
+impl Document {
+    /// 子孫にタスクを持つヘッダとタスクの上に、進捗を表示する。
+    pub fn get_code_lenses(&self, url: &Url, today: NaiveDate) -> Vec<CodeLens> {
+        let outline = self.outline();
+        (0..outline.nodes().len())
+            .filter(|&idx| !outline.node(idx).is_memo())
+            .filter_map(|idx| {
+                let node = outline.node(idx);
+                let progress = self.progress(idx, today)?;
+                let range = node.range.try_pos_into(self)?;
+                Some(CodeLens {
+                    range,
+                    command: Some(Command {
+                        title: progress.to_string(),
+                        command: LIST_OPEN_CHILDREN.to_owned(),
+                        arguments: Some(vec![json!(url), json!(node.row)]),
+                    }),
+                    data: None,
+                })
+            })
+            .collect()
+    }
+
+    /// 指定した行にある節の子孫のうち、未完了のタスクの行番号と本文。
+    pub fn get_open_children(&self, row: usize) -> Vec<(usize, String)> {
+        let outline = self.outline();
+        let Some(idx) = outline.find_by_row(row) else {
+            return vec![];
+        };
+        outline
+            .descendants(idx)
+            .into_iter()
+            .filter(|&child| {
+                outline.node(child).is_task() && !outline.attributes(child).is_finished()
+            })
+            .map(|child| {
+                let node = outline.node(child);
+                (node.row, node.text().unwrap_or_default())
+            })
+            .collect()
+    }
+
+    fn progress(&self, idx: usize, today: NaiveDate) -> Option<Progress> {
+        let outline = self.outline();
+        let tasks = outline
+            .descendants(idx)
+            .into_iter()
+            .filter(|&child| outline.node(child).is_task())
+            .map(|child| outline.attributes(child))
+            .collect::<Vec<_>>();
+        if tasks.is_empty() {
+            return None;
+        }
+
+        let mut progress = Progress {
+            total: 0,
+            done: 0,
+            overdue: 0,
+            next_due: None,
+        };
+        for attrs in tasks {
+            match attrs.status_kind() {
+                StatusKind::Cancel => continue,
+                StatusKind::Done => {
+                    progress.total += 1;
+                    progress.done += 1;
+                    continue;
+                }
+                _ => progress.total += 1,
+            }
+            if let Some(deadline) = &attrs.dates[2] {
+                if deadline.value < today {
+                    progress.overdue += 1;
+                } else if progress.next_due.is_none_or(|due| deadline.value < due) {
+                    progress.next_due = Some(deadline.value);
+                }
+            }
+        }
+        Some(progress)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_progress() {
+        let today = NaiveDate::from_ymd_opt(2021, 11, 10).unwrap();
+        let document = Document::parse(
+            "[work]\n\t- done\n\t= cancelled\n\t(2021-11-01!) late\n\t(2021-11-20!) soon\n\t\t(2021-11-12!) sooner\n"
+                .to_owned(),
+        )
+        .unwrap();
+        let header = document.progress(0, today).unwrap();
+        assert_eq!(
+            header.to_string(),
+            "1/4 done · 1 overdue · next due 2021-11-12"
+        );
+        assert_eq!(
+            document
+                .get_open_children(0)
+                .into_iter()
+                .map(|(row, _)| row)
+                .collect::<Vec<_>>(),
+            vec![3, 4, 5]
+        );
+        assert_eq!(document.progress(1, today), None);
+    }
+}
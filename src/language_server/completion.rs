@@ -1,7 +1,7 @@
 use std::collections::HashSet;
 
 use anyhow::*;
-use chrono::{Duration, Local};
+use chrono::{Duration, NaiveDate};
 use log::debug;
 use tower_lsp::lsp_types::{CompletionItem, CompletionTextEdit, TextEdit};
 use tree_sitter::Point;
@@ -9,10 +9,17 @@ use tree_sitter_todome::syntax::ast::{AstNode, Category, Tag};
 
 use crate::structure::{position::PosInto, syntax::Document};
 
+use super::config::CompletionConfig;
+
 impl Document {
+    /// 補完候補を返す。カテゴリとタグは `workspace` の各ドキュメントに現れるものも候補にする。
+    /// 日付の候補は `today` からの日数で作る。
     pub fn get_completion(
         &self,
         params: &tower_lsp::lsp_types::CompletionParams,
+        workspace: &[&Document],
+        config: &CompletionConfig,
+        today: NaiveDate,
     ) -> Result<Vec<CompletionItem>> {
         let cursor = {
             let cursor = params.text_document_position.position;
@@ -41,15 +48,15 @@ impl Document {
         let completions = match (trigger_character, rule) {
             (Some("["), _) | (_, Some("category")) => {
                 // category name の completion
-                self.get_category_completions(cursor)
+                self.get_category_completions(cursor, workspace)
             }
             (Some("("), _) | (_, Some("due")) | (_, Some("priority")) => {
                 // due の completion
-                self.get_due_completion(cursor)
+                self.get_due_completion(cursor, config, today)
             }
             (Some("@"), _) | (_, Some("tag")) => {
                 // tag name の completion
-                self.get_tag_completions(cursor)
+                self.get_tag_completions(cursor, workspace)
             }
             _ => return Ok(vec![]),
         };
@@ -57,7 +64,11 @@ impl Document {
         Ok(completions)
     }
 
-    fn get_category_completions(&self, cursor: usize) -> Vec<CompletionItem> {
+    fn get_category_completions(
+        &self,
+        cursor: usize,
+        workspace: &[&Document],
+    ) -> Vec<CompletionItem> {
         let range = {
             let row = {
                 let point: Option<Point> = cursor.try_pos_into(self);
@@ -76,11 +87,9 @@ impl Document {
                 .unwrap()
         };
 
-        let categories: HashSet<String> = self
-            .root()
-            .syntax()
-            .children_recursive()
-            .into_iter()
+        let categories: HashSet<String> = std::iter::once(self)
+            .chain(workspace.iter().copied())
+            .flat_map(|document| document.root().syntax().children_recursive())
             .filter_map(Category::cast)
             .map(|category| category.name())
             .collect();
@@ -94,6 +103,7 @@ impl Document {
                 };
                 CompletionItem {
                     label: new_text,
+                    label_details: None,
                     kind: None,
                     detail: None,
                     documentation: None,
@@ -115,7 +125,7 @@ impl Document {
             .collect()
     }
 
-    fn get_tag_completions(&self, cursor: usize) -> Vec<CompletionItem> {
+    fn get_tag_completions(&self, cursor: usize, workspace: &[&Document]) -> Vec<CompletionItem> {
         let range = {
             let row = {
                 let point: Option<Point> = cursor.try_pos_into(self);
@@ -133,11 +143,9 @@ impl Document {
                 .unwrap()
         };
 
-        let tags: HashSet<String> = self
-            .root()
-            .syntax()
-            .children_recursive()
-            .into_iter()
+        let tags: HashSet<String> = std::iter::once(self)
+            .chain(workspace.iter().copied())
+            .flat_map(|document| document.root().syntax().children_recursive())
             .filter_map(Tag::cast)
             .map(|tag| tag.name())
             .collect();
@@ -150,6 +158,7 @@ impl Document {
                 };
                 CompletionItem {
                     label: new_text,
+                    label_details: None,
                     kind: None,
                     detail: None,
                     documentation: None,
@@ -171,7 +180,12 @@ impl Document {
             .collect()
     }
 
-    fn get_due_completion(&self, cursor: usize) -> Vec<CompletionItem> {
+    fn get_due_completion(
+        &self,
+        cursor: usize,
+        config: &CompletionConfig,
+        today: NaiveDate,
+    ) -> Vec<CompletionItem> {
         let range = {
             let row = {
                 let point: Option<Point> = cursor.try_pos_into(self);
@@ -190,25 +204,23 @@ impl Document {
                 .unwrap()
         };
 
-        let now = Local::now().naive_local().date();
-        let candidates = [
-            (now, "today"),
-            (now + Duration::days(1), "tomorrow"),
-            (now + Duration::days(2), "2 days later"),
-            (now + Duration::days(7), "1 week later"),
-        ];
-        candidates
-            .into_iter()
-            .map(|(date, desc)| {
+        config
+            .date_offsets
+            .iter()
+            // 設定された日数が大きすぎて日付を表せなければ候補にしない。
+            .filter_map(|&days| {
+                let date = today.checked_add_signed(Duration::try_days(days)?)?;
+                let desc = describe_offset(days);
                 let new_text = format!("({})", date.format("%Y-%m-%d"));
                 let edit = TextEdit {
                     range,
                     new_text: new_text.clone(),
                 };
-                CompletionItem {
+                Some(CompletionItem {
                     label: new_text,
+                    label_details: None,
                     kind: None,
-                    detail: Some(desc.to_owned()),
+                    detail: Some(desc),
                     documentation: None,
                     deprecated: None,
                     preselect: None,
@@ -223,8 +235,21 @@ impl Document {
                     commit_characters: None,
                     data: None,
                     tags: None,
-                }
+                })
             })
             .collect()
     }
 }
+
+/// 今日からの日数を "tomorrow" や "1 week later" のように表す。
+fn describe_offset(days: i64) -> String {
+    match days {
+        0 => "today".to_owned(),
+        1 => "tomorrow".to_owned(),
+        -1 => "yesterday".to_owned(),
+        7 => "1 week later".to_owned(),
+        n if n > 0 && n % 7 == 0 => format!("{} weeks later", n / 7),
+        n if n > 0 => format!("{} days later", n),
+        n => format!("{} days ago", -n),
+    }
+}
@@ -0,0 +1,166 @@
+use chrono::{Local, NaiveDate};
+use serde::{Deserialize, Deserializer};
+use tower_lsp::lsp_types::DiagnosticSeverity;
+
+/// language server の設定。
+///
+/// `initializationOptions` や `workspace/configuration` の `todome` セクションから読む。
+/// キーは camelCase で、書かれていない項目は既定値になる。
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Config {
+    /// 改行したとき、前の行の status 記号を新しい行にも書き入れる。
+    pub carry_over_status: bool,
+    /// 完了・キャンセル済みのタスクの折り畳み範囲を Imports として返す。
+    /// 多くのクライアントは Imports の範囲を既定で折り畳むので、完了したタスクが畳まれた状態で開かれる。
+    pub fold_completed_as_imports: bool,
+    /// 完了したタスクのアーカイブ先とするファイル名。ドキュメントと同じディレクトリに置く。
+    /// 指定しなければドキュメント末尾の `# Archive` 行の下に移す。
+    pub archive_file: Option<String>,
+    /// 今日の日付として扱う日付（`2021-11-10` の形式）。日付に依存する表示を確かめるときに使う。
+    #[serde(deserialize_with = "deserialize_date")]
+    pub today: Option<NaiveDate>,
+    pub diagnostics: DiagnosticsConfig,
+    pub completion: CompletionConfig,
+}
+
+impl Config {
+    /// クライアントから受け取った設定を読む。`{ "todome": { ... } }` の形でも受け付ける。
+    pub fn from_value(value: serde_json::Value) -> serde_json::Result<Config> {
+        let value = match value {
+            serde_json::Value::Object(mut map) if map.contains_key("todome") => {
+                map.remove("todome").unwrap_or_default()
+            }
+            value => value,
+        };
+        if value.is_null() {
+            return Ok(Config::default());
+        }
+        serde_json::from_value(value)
+    }
+
+    /// 今日の日付。`today` が設定されていればそれを使う。
+    pub fn today(&self) -> NaiveDate {
+        self.today
+            .unwrap_or_else(|| Local::now().naive_local().date())
+    }
+}
+
+fn deserialize_date<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<NaiveDate>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|date| NaiveDate::parse_from_str(&date, "%Y-%m-%d").map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+/// diagnostics の設定。各項目の重大度を `"off"` にするとその検査をしない。
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct DiagnosticsConfig {
+    /// 締切がこの日数以内に迫っていれば知らせる。
+    pub upcoming_deadline_days: i64,
+    /// 構文エラー。
+    pub syntax_error: Severity,
+    /// 開始日・目標日・締切の順序の矛盾。
+    pub date_order: Severity,
+    /// 開始日より前のタスク。
+    pub not_started: Severity,
+    /// 目標日を過ぎたタスク。
+    pub target_passed: Severity,
+    /// 目標日が今日のタスク。
+    pub target_today: Severity,
+    /// 締切を過ぎたタスク。
+    pub overdue: Severity,
+    /// 締切が今日のタスク。
+    pub due_today: Severity,
+    /// 締切が迫っているタスク。
+    pub deadline_upcoming: Severity,
+}
+
+impl Default for DiagnosticsConfig {
+    fn default() -> Self {
+        Self {
+            upcoming_deadline_days: 7,
+            syntax_error: Severity::Error,
+            date_order: Severity::Error,
+            not_started: Severity::Hint,
+            target_passed: Severity::Warning,
+            target_today: Severity::Information,
+            overdue: Severity::Error,
+            due_today: Severity::Warning,
+            deadline_upcoming: Severity::Information,
+        }
+    }
+}
+
+/// diagnostics の重大度。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Severity {
+    Off,
+    Error,
+    Warning,
+    Information,
+    Hint,
+}
+
+impl Severity {
+    /// LSP の重大度。`Off` なら `None`。
+    pub fn to_lsp(self) -> Option<DiagnosticSeverity> {
+        match self {
+            Severity::Off => None,
+            Severity::Error => Some(DiagnosticSeverity::ERROR),
+            Severity::Warning => Some(DiagnosticSeverity::WARNING),
+            Severity::Information => Some(DiagnosticSeverity::INFORMATION),
+            Severity::Hint => Some(DiagnosticSeverity::HINT),
+        }
+    }
+}
+
+/// 補完の設定。
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct CompletionConfig {
+    /// 日付の補完候補とする、今日からの日数。
+    pub date_offsets: Vec<i64>,
+}
+
+impl Default for CompletionConfig {
+    fn default() -> Self {
+        Self {
+            date_offsets: vec![0, 1, 2, 7],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_from_value() {
+        let config = Config::from_value(json!({
+            "todome": {
+                "carryOverStatus": true,
+                "today": "2021-11-10",
+                "diagnostics": { "upcomingDeadlineDays": 3, "notStarted": "off" },
+            }
+        }))
+        .unwrap();
+        assert!(config.carry_over_status);
+        assert_eq!(
+            config.today(),
+            NaiveDate::from_ymd_opt(2021, 11, 10).unwrap()
+        );
+        assert_eq!(config.diagnostics.upcoming_deadline_days, 3);
+        assert_eq!(config.diagnostics.not_started, Severity::Off);
+        assert_eq!(config.diagnostics.overdue, Severity::Error);
+        assert_eq!(config.completion, CompletionConfig::default());
+
+        assert!(Config::from_value(json!(null)).is_ok());
+        assert!(Config::from_value(json!({ "diagnostics": { "overdue": "fatal" } })).is_err());
+    }
+}
@@ -0,0 +1,88 @@
+use tree_sitter_todome::syntax::ast::Meta;
+
+use crate::structure::syntax::Document;
+
+use super::occurrence::Referent;
+
+impl Document {
+    /// カーソル位置から辿れる定義の範囲。
+    ///
+    /// * カテゴリの上では、そのカテゴリを持つ最初のヘッダ。
+    /// * タスクの上では、親タスクと、継承している属性（status, 優先度, 日付, カテゴリ）が書かれた節。
+    pub fn get_definitions(&self, pos: usize) -> Vec<(usize, usize)> {
+        match self.find_referent(pos) {
+            Some((Referent::Category(name), _)) => {
+                return self.find_category_header(&name).into_iter().collect();
+            }
+            Some((Referent::Tag(_), _)) => return vec![],
+            None => {}
+        }
+
+        let outline = self.outline();
+        let row = match self.lines().binary_search(&pos) {
+            Ok(row) => row,
+            Err(row) => row - 1,
+        };
+        let idx = match outline.find_by_row(row) {
+            Some(idx) if outline.node(idx).is_task() => idx,
+            _ => return vec![],
+        };
+
+        let attrs = outline.attributes(idx);
+        let mut sources = vec![];
+        if let Some(parent) = outline.node(idx).parent {
+            if outline.node(parent).is_task() {
+                sources.push(parent);
+            }
+        }
+        sources.extend(attrs.status.map(|status| status.source));
+        sources.extend(attrs.priority.map(|priority| priority.source));
+        sources.extend(attrs.dates.into_iter().flatten().map(|date| date.source));
+        sources.extend(attrs.categories.into_iter().map(|category| category.source));
+
+        sources.sort_unstable();
+        sources.dedup();
+        sources
+            .into_iter()
+            .filter(|&source| source != idx)
+            .map(|source| outline.node(source).range)
+            .collect()
+    }
+
+    /// カテゴリを持つ最初のヘッダの範囲。
+    pub fn find_category_header(&self, name: &str) -> Option<(usize, usize)> {
+        self.outline()
+            .nodes()
+            .iter()
+            .filter(|node| node.is_header())
+            .find(|node| {
+                node.meta()
+                    .into_iter()
+                    .any(|meta| matches!(meta, Meta::Category(category) if category.name() == name))
+            })
+            .map(|node| node.range)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_definitions() {
+        let text = "- [work]\n\t(A) parent\n\t\tchild\n[work] other\n";
+        let document = Document::parse(text.to_owned()).unwrap();
+        let row_of = |range: (usize, usize)| document.text()[..range.0].matches('\n').count();
+        let rows = |pos: usize| {
+            document
+                .get_definitions(pos)
+                .into_iter()
+                .map(row_of)
+                .collect::<Vec<_>>()
+        };
+        // 子タスクからは親タスクと、status・カテゴリを継承しているヘッダへ。
+        assert_eq!(rows(text.find("child").unwrap()), vec![0, 1]);
+        // 4 行目のカテゴリからは、そのカテゴリを持つヘッダへ。
+        assert_eq!(rows(text.rfind("[work]").unwrap() + 1), vec![0]);
+    }
+}
@@ -1,9 +1,19 @@
-use chrono::{Duration, Local, NaiveDate};
-use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, DiagnosticTag};
+use chrono::NaiveDate;
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+};
+
+use tower_lsp::lsp_types::{
+    Diagnostic, DiagnosticTag, DocumentDiagnosticReportKind, FullDocumentDiagnosticReport,
+    UnchangedDocumentDiagnosticReport,
+};
 use tree_sitter_todome::syntax::ast::{AstNode, Task};
 
 use crate::structure::{position::PosInto, syntax::Document};
 
+use super::config::DiagnosticsConfig;
+
 fn default_diag() -> Diagnostic {
     Diagnostic {
         source: Some("todome".to_owned()),
@@ -12,12 +22,39 @@ fn default_diag() -> Diagnostic {
 }
 
 impl Document {
-    pub fn get_diagnostics(&self) -> Vec<Diagnostic> {
-        let today = Local::now().naive_local().date();
-        [self.get_syntax_error(), self.get_date_diagnostics(today)].concat()
+    pub fn get_diagnostics(&self, today: NaiveDate, config: &DiagnosticsConfig) -> Vec<Diagnostic> {
+        [
+            self.get_syntax_error(config),
+            self.get_date_diagnostics(today, config),
+        ]
+        .concat()
+        .into_iter()
+        // 重大度が Off の検査は結果を捨てる。
+        .filter(|diag| diag.severity.is_some())
+        .collect()
     }
 
-    fn get_syntax_error(&self) -> Vec<Diagnostic> {
+    /// pull 型の diagnostics の結果。内容が `previous_result_id` のときから変わっていなければ
+    /// Unchanged を返す。result id は内容から決めるので、日付が変わっても結果が同じなら Unchanged になる。
+    pub fn get_diagnostic_report(
+        &self,
+        today: NaiveDate,
+        config: &DiagnosticsConfig,
+        previous_result_id: Option<&str>,
+    ) -> DocumentDiagnosticReportKind {
+        let items = self.get_diagnostics(today, config);
+        let result_id = result_id(&items);
+        if previous_result_id == Some(result_id.as_str()) {
+            DocumentDiagnosticReportKind::Unchanged(UnchangedDocumentDiagnosticReport { result_id })
+        } else {
+            DocumentDiagnosticReportKind::Full(FullDocumentDiagnosticReport {
+                result_id: Some(result_id),
+                items,
+            })
+        }
+    }
+
+    fn get_syntax_error(&self, config: &DiagnosticsConfig) -> Vec<Diagnostic> {
         self.root()
             .syntax()
             .children_recursive()
@@ -27,7 +64,7 @@ impl Document {
                     let range = n.range().try_pos_into(self)?;
                     Some(Diagnostic {
                         range,
-                        severity: Some(DiagnosticSeverity::Error),
+                        severity: config.syntax_error.to_lsp(),
                         code: None,
                         code_description: None,
                         source: Some("todome".to_owned()),
@@ -43,7 +80,11 @@ impl Document {
             .collect()
     }
 
-    fn get_date_diagnostics(&self, today: NaiveDate) -> Vec<Diagnostic> {
+    fn get_date_diagnostics(
+        &self,
+        today: NaiveDate,
+        config: &DiagnosticsConfig,
+    ) -> Vec<Diagnostic> {
         self.root()
             .items_nested()
             .into_iter()
@@ -57,7 +98,7 @@ impl Document {
             })
             .flat_map(|item| {
                 if let Some(task) = item.as_task() {
-                    self.get_date_diags_for_task(task, today)
+                    self.get_date_diags_for_task(task, today, config)
                 } else {
                     vec![]
                 }
@@ -66,19 +107,25 @@ impl Document {
     }
 
     /// 特定のタスクに対し、日付に関連する diagnostics を生成する。
+    /// 括弧内は既定の重大度で、`config` で変えられる。
     ///
     /// * 日付設定に矛盾がある
-    ///     * [ERROR] start <= target <= deadline が満たされていない
+    ///     * [ERROR] start <= target <= deadline が満たされていない (`date_order`)
     /// * 開始前
-    ///     * [INFO (unused)] start > today
+    ///     * [HINT (unused)] start > today (`not_started`)
     /// * 期日が近い
-    ///     * [INFO] deadline < today + 7
-    ///     * [INFO] target == today
-    ///     * [WARNING] deadline == today
+    ///     * [INFO] deadline < today + 7 (`deadline_upcoming`, 日数は `upcoming_deadline_days`)
+    ///     * [INFO] target == today (`target_today`)
+    ///     * [WARNING] deadline == today (`due_today`)
     /// * 期日を過ぎている
-    ///     * [ERROR] deadline < today
-    ///     * [WARNING] target < today
-    fn get_date_diags_for_task(&self, task: &Task, today: NaiveDate) -> Vec<Diagnostic> {
+    ///     * [ERROR] deadline < today (`overdue`)
+    ///     * [WARNING] target < today (`target_passed`)
+    fn get_date_diags_for_task(
+        &self,
+        task: &Task,
+        today: NaiveDate,
+        config: &DiagnosticsConfig,
+    ) -> Vec<Diagnostic> {
         let Some(date) = task.meta().into_iter().find_map(|meta| meta.as_date().cloned())
         else {
             return vec![]
@@ -99,7 +146,7 @@ impl Document {
                     .expect("failed to convert position.");
                 diags.push(Diagnostic {
                     range,
-                    severity: Some(DiagnosticSeverity::Error),
+                    severity: config.date_order.to_lsp(),
                     message: "start date must be earlier than target date.".to_owned(),
                     ..default_diag()
                 })
@@ -115,7 +162,7 @@ impl Document {
                     .expect("failed to convert position.");
                 diags.push(Diagnostic {
                     range,
-                    severity: Some(DiagnosticSeverity::Error),
+                    severity: config.date_order.to_lsp(),
                     message: "target date must be earlier than deadline.".to_owned(),
                     ..default_diag()
                 })
@@ -131,7 +178,7 @@ impl Document {
                     .expect("failed to convert position.");
                 diags.push(Diagnostic {
                     range,
-                    severity: Some(DiagnosticSeverity::Error),
+                    severity: config.date_order.to_lsp(),
                     message: "start date must be earlier than deadline.".to_owned(),
                     ..default_diag()
                 })
@@ -147,9 +194,9 @@ impl Document {
                     .expect("failed to convert position.");
                 diags.push(Diagnostic {
                     range,
-                    severity: Some(DiagnosticSeverity::Hint),
+                    severity: config.not_started.to_lsp(),
                     message: "this task is not started yet.".to_owned(),
-                    tags: Some(vec![DiagnosticTag::Unnecessary]),
+                    tags: Some(vec![DiagnosticTag::UNNECESSARY]),
                     ..default_diag()
                 })
             }
@@ -164,13 +211,13 @@ impl Document {
             match target {
                 target if today > target => diags.push(Diagnostic {
                     range,
-                    severity: Some(DiagnosticSeverity::Warning),
+                    severity: config.target_passed.to_lsp(),
                     message: "target date of this task is over.".to_owned(),
                     ..default_diag()
                 }),
                 target if today == target => diags.push(Diagnostic {
                     range,
-                    severity: Some(DiagnosticSeverity::Information),
+                    severity: config.target_today.to_lsp(),
                     message: "this task is targeted today.".to_owned(),
                     ..default_diag()
                 }),
@@ -184,25 +231,29 @@ impl Document {
                 .range()
                 .try_pos_into(self)
                 .expect("failed to convert position.");
+            // 日数は設定で与えられるので、日付の加減算で溢れないように差で比べる。
+            let upcoming_days = config.upcoming_deadline_days;
             match deadline {
                 deadline if today > deadline => diags.push(Diagnostic {
                     range,
-                    severity: Some(DiagnosticSeverity::Error),
+                    severity: config.overdue.to_lsp(),
                     message: "this task is OVERDUE!".to_owned(),
                     ..default_diag()
                 }),
                 deadline if today == deadline => diags.push(Diagnostic {
                     range,
-                    severity: Some(DiagnosticSeverity::Warning),
+                    severity: config.due_today.to_lsp(),
                     message: "this task is due today.".to_owned(),
                     ..default_diag()
                 }),
-                deadline if today >= deadline - Duration::days(7) => diags.push(Diagnostic {
-                    range,
-                    severity: Some(DiagnosticSeverity::Information),
-                    message: "deadline is coming up.".to_owned(),
-                    ..default_diag()
-                }),
+                deadline if (deadline - today).num_days() <= upcoming_days => {
+                    diags.push(Diagnostic {
+                        range,
+                        severity: config.deadline_upcoming.to_lsp(),
+                        message: "deadline is coming up.".to_owned(),
+                        ..default_diag()
+                    })
+                }
                 _ => {}
             }
         }
@@ -210,3 +261,59 @@ impl Document {
         diags
     }
 }
+
+/// diagnostics の内容のハッシュ。
+fn result_id(diags: &[Diagnostic]) -> String {
+    let mut hasher = DefaultHasher::new();
+    serde_json::to_string(diags)
+        .unwrap_or_default()
+        .hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::language_server::config::Severity;
+
+    use super::*;
+
+    #[test]
+    fn test_diagnostic_report() {
+        let today = NaiveDate::from_ymd_opt(2021, 11, 10).unwrap();
+        let config = DiagnosticsConfig::default();
+        let document = Document::parse("(2021-11-01!) late\n".to_owned()).unwrap();
+        let result_id = match document.get_diagnostic_report(today, &config, None) {
+            DocumentDiagnosticReportKind::Full(report) => {
+                assert_eq!(report.items.len(), 1);
+                report.result_id.unwrap()
+            }
+            _ => panic!("expected a full report"),
+        };
+        assert!(matches!(
+            document.get_diagnostic_report(today, &config, Some(&result_id)),
+            DocumentDiagnosticReportKind::Unchanged(_)
+        ));
+
+        let config = DiagnosticsConfig {
+            overdue: Severity::Off,
+            ..DiagnosticsConfig::default()
+        };
+        match document.get_diagnostic_report(today, &config, Some(&result_id)) {
+            DocumentDiagnosticReportKind::Full(report) => assert!(report.items.is_empty()),
+            _ => panic!("expected a full report"),
+        }
+    }
+
+    #[test]
+    fn test_extreme_upcoming_deadline_days() {
+        let today = NaiveDate::from_ymd_opt(2021, 11, 10).unwrap();
+        let document = Document::parse("(2021-11-12!) soon\n".to_owned()).unwrap();
+        for (days, expected) in [(i64::MAX, 1), (i64::MIN, 0), (1, 0), (2, 1)] {
+            let config = DiagnosticsConfig {
+                upcoming_deadline_days: days,
+                ..DiagnosticsConfig::default()
+            };
+            assert_eq!(document.get_diagnostics(today, &config).len(), expected);
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use tower_lsp::lsp_types::{FoldingRange, FoldingRangeKind};
+
+use crate::structure::syntax::Document;
+
+use super::config::Config;
+
+impl Document {
+    /// 子を持つタスク・ヘッダと、メモだけの行が連続する部分を折り畳めるようにする。
+    pub fn get_folding_ranges(&self, config: &Config) -> Vec<FoldingRange> {
+        let outline = self.outline();
+        let mut ranges = vec![];
+
+        for (idx, node) in outline.nodes().iter().enumerate() {
+            if node.is_memo() {
+                continue;
+            }
+            let last_row = outline.last_row(idx);
+            if last_row <= node.row {
+                continue;
+            }
+            // 完了したタスクは Imports として返し、既定で折り畳むようクライアントに促す。
+            let kind = if config.fold_completed_as_imports
+                && node.is_task()
+                && outline.attributes(idx).is_finished()
+            {
+                Some(FoldingRangeKind::Imports)
+            } else {
+                None
+            };
+            ranges.push(FoldingRange {
+                start_line: node.row as u32,
+                start_character: None,
+                end_line: last_row as u32,
+                end_character: None,
+                kind,
+                collapsed_text: None,
+            });
+        }
+
+        let memo_rows = outline
+            .nodes()
+            .iter()
+            .filter(|node| node.is_memo())
+            .map(|node| node.row)
+            .collect::<Vec<_>>();
+        let mut start = 0;
+        for i in 1..=memo_rows.len() {
+            if i < memo_rows.len() && memo_rows[i] == memo_rows[i - 1] + 1 {
+                continue;
+            }
+            if i - start >= 2 {
+                ranges.push(FoldingRange {
+                    start_line: memo_rows[start] as u32,
+                    start_character: None,
+                    end_line: memo_rows[i - 1] as u32,
+                    end_character: None,
+                    kind: Some(FoldingRangeKind::Comment),
+                    collapsed_text: None,
+                });
+            }
+            start = i;
+        }
+
+        ranges
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_folding_ranges() {
+        let text = "[work]\n\t- done\n\t\tsub\n\ttodo\n# memo\n# memo\n";
+        let document = Document::parse(text.to_owned()).unwrap();
+        let config = Config {
+            fold_completed_as_imports: true,
+            ..Config::default()
+        };
+        let ranges = document
+            .get_folding_ranges(&config)
+            .into_iter()
+            .map(|range| (range.start_line, range.end_line, range.kind))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ranges,
+            vec![
+                (0, 3, None),
+                (1, 2, Some(FoldingRangeKind::Imports)),
+                (4, 5, Some(FoldingRangeKind::Comment)),
+            ]
+        );
+    }
+}
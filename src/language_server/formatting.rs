@@ -0,0 +1,204 @@
+use anyhow::*;
+use itertools::Itertools;
+use tower_lsp::lsp_types::{DocumentOnTypeFormattingParams, TextEdit};
+use tree_sitter_todome::syntax::ast::{AstNode, Item};
+
+use crate::{
+    structure::{position::PosInto, syntax::Document},
+    subcmd::format::{format_lines, FormatOptions},
+};
+
+use super::config::Config;
+
+impl Document {
+    /// ドキュメント全体をフォーマットするための編集を返す。
+    ///
+    /// カーソル位置や undo 履歴を保つため、内容が変わる行だけを置き換える。
+    pub fn get_formatting_edits(&self, options: &FormatOptions) -> Result<Vec<TextEdit>> {
+        let formatted = format_lines(self.text(), options);
+        let new_lines = formatted.text.lines().collect_vec();
+        let old_lines = self.text().lines().collect_vec();
+
+        if new_lines.len() != old_lines.len() {
+            // 行の対応が取れない場合はドキュメント全体を置き換える
+            let range = (0, self.text().len())
+                .try_pos_into(self)
+                .ok_or_else(|| anyhow!("failed to convert position."))?;
+            return Ok(vec![TextEdit {
+                range,
+                new_text: formatted.text,
+            }]);
+        }
+
+        let edits = old_lines
+            .into_iter()
+            .zip(new_lines)
+            .enumerate()
+            .filter(|(_, (old, new))| old != new)
+            .filter_map(|(row, (old, new))| {
+                let start = self.lines()[row];
+                let range = (start, start + old.len()).try_pos_into(self)?;
+                Some(TextEdit {
+                    range,
+                    new_text: new.to_owned(),
+                })
+            })
+            .collect();
+        Ok(edits)
+    }
+}
+
+impl Document {
+    /// 入力に合わせて行頭のインデントを整える。
+    ///
+    /// * 改行したとき: 新しい行に前の行と同じだけ TAB でインデントを入れる。
+    ///   前の行がヘッダなら 1 段深くする。
+    /// * 行頭で空白を打ったとき: 行頭の空白を TAB に置き換える。本文中で打った空白には何もしない。
+    pub fn get_on_type_formatting(
+        &self,
+        params: &DocumentOnTypeFormattingParams,
+        config: &Config,
+    ) -> Vec<TextEdit> {
+        let row = params.text_document_position.position.line as usize;
+        let Some(line) = self.line(row) else {
+            return vec![];
+        };
+        let start_of_line = self.lines()[row];
+        let leading = line.len() - line.trim_start_matches([' ', '\t']).len();
+
+        let new_leading = match params.ch.as_str() {
+            "\n" => self.indent_for_new_line(row, config),
+            " " => {
+                // 行頭の空白は ASCII なので、UTF-16 での位置とバイト数を比べてよい
+                let character = params.text_document_position.position.character as usize;
+                if character > leading || !line[..leading].contains(' ') {
+                    return vec![];
+                }
+                let tab_size = (params.options.tab_size as usize).max(1);
+                let width: usize = line[..leading]
+                    .chars()
+                    .map(|c| if c == '\t' { tab_size } else { 1 })
+                    .sum();
+                format!(
+                    "{}{}",
+                    "\t".repeat(width / tab_size),
+                    " ".repeat(width % tab_size)
+                )
+            }
+            _ => return vec![],
+        };
+        if new_leading == line[..leading] {
+            return vec![];
+        }
+
+        let Some(range) = (start_of_line, start_of_line + leading).try_pos_into(self) else {
+            return vec![];
+        };
+        vec![TextEdit {
+            range,
+            new_text: new_leading,
+        }]
+    }
+
+    /// `row` 行目に新しく入力される行の行頭に置く文字列（インデントと status 記号）を求める。
+    fn indent_for_new_line(&self, row: usize, config: &Config) -> String {
+        let Some(prev_row) = (0..row)
+            .rev()
+            .find(|&r| self.line(r).is_some_and(|l| !l.trim().is_empty()))
+        else {
+            return String::new();
+        };
+        let prev_line = self.line(prev_row).unwrap_or_default();
+        let indent = prev_line.len() - prev_line.trim_start_matches('\t').len();
+
+        let prev_item = self
+            .outline()
+            .find_by_row(prev_row)
+            .map(|idx| &self.outline().node(idx).item);
+
+        match prev_item {
+            Some(Item::Header(_)) => "\t".repeat(indent + 1),
+            Some(Item::Task(task)) if config.carry_over_status => {
+                let line = self.line(row).unwrap_or_default();
+                let has_status = ["+ ", "* ", "- ", "= "]
+                    .iter()
+                    .any(|s| line.trim_start().starts_with(s));
+                let status = task
+                    .status()
+                    .filter(|_| !has_status)
+                    .map(|status| {
+                        let (start, end) = status.syntax().range();
+                        format!("{} ", &self.text()[start..end])
+                    })
+                    .unwrap_or_default();
+                format!("{}{}", "\t".repeat(indent), status)
+            }
+            _ => "\t".repeat(indent),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::{Position, Range};
+
+    use super::*;
+
+    #[test]
+    fn test_formatting_edits() {
+        let document = Document::parse("- done\n[work] (A) task\n\tsub\n".to_owned()).unwrap();
+        let edits = document
+            .get_formatting_edits(&FormatOptions::default())
+            .unwrap();
+        assert_eq!(
+            edits,
+            vec![TextEdit {
+                range: Range {
+                    start: Position::new(1, 0),
+                    end: Position::new(1, 15),
+                },
+                new_text: "(A) [work] task".to_owned(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_on_type_formatting() {
+        use tower_lsp::lsp_types::{
+            FormattingOptions, TextDocumentIdentifier, TextDocumentPositionParams, Url,
+        };
+
+        let params = |line, character, ch: &str| DocumentOnTypeFormattingParams {
+            text_document_position: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier {
+                    uri: Url::parse("file:///test.todome").unwrap(),
+                },
+                position: Position::new(line, character),
+            },
+            ch: ch.to_owned(),
+            options: FormattingOptions {
+                tab_size: 4,
+                insert_spaces: false,
+                ..Default::default()
+            },
+        };
+        let config = Config {
+            carry_over_status: true,
+            ..Config::default()
+        };
+
+        let document =
+            Document::parse("[work]\n\n\t* task\n    \n    task a\n".to_owned()).unwrap();
+        let edits = document.get_on_type_formatting(&params(1, 0, "\n"), &config);
+        assert_eq!(edits[0].new_text, "\t");
+        let edits = document.get_on_type_formatting(&params(3, 0, "\n"), &config);
+        assert_eq!(edits[0].new_text, "\t* ");
+        let edits = document.get_on_type_formatting(&params(3, 4, " "), &config);
+        assert_eq!(edits[0].new_text, "\t");
+        let edits = document.get_on_type_formatting(&params(4, 4, " "), &config);
+        assert_eq!(edits[0].new_text, "\t");
+        // 本文中の空白では行頭を書き換えない
+        let edits = document.get_on_type_formatting(&params(4, 9, " "), &config);
+        assert!(edits.is_empty());
+    }
+}
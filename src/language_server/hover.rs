@@ -0,0 +1,190 @@
+use chrono::NaiveDate;
+use itertools::Itertools;
+use tower_lsp::lsp_types::{Hover, HoverContents, HoverParams, MarkupContent, MarkupKind};
+use tree_sitter_todome::syntax::ast::{AstNode, Category, StatusKind, Tag};
+
+use crate::structure::{
+    outline::{Inherited, Outline},
+    position::PosInto,
+    syntax::Document,
+};
+
+impl Document {
+    pub fn get_hover(&self, params: &HoverParams, today: NaiveDate) -> Option<Hover> {
+        let cursor: usize = params
+            .text_document_position_params
+            .position
+            .try_pos_into(self)?;
+
+        let nodes = self.root().syntax().dig(cursor);
+        if let Some(category) = nodes.iter().cloned().find_map(Category::cast) {
+            return self.get_category_hover(&category);
+        }
+        if let Some(tag) = nodes.iter().cloned().find_map(Tag::cast) {
+            return self.get_tag_hover(&tag);
+        }
+
+        let row = params.text_document_position_params.position.line as usize;
+        let idx = self.outline().find_by_row(row)?;
+        self.get_task_hover(idx, today)
+    }
+
+    /// タスクの（継承を解決した後の）属性を表示する。
+    fn get_task_hover(&self, idx: usize, today: NaiveDate) -> Option<Hover> {
+        let outline = self.outline();
+        let node = outline.node(idx);
+        if !node.is_task() {
+            return None;
+        }
+        let attrs = outline.attributes(idx);
+
+        let mut lines = vec![];
+        let status = match attrs.status_kind() {
+            StatusKind::Todo => "todo",
+            StatusKind::Doing => "doing",
+            StatusKind::Done => "done",
+            StatusKind::Cancel => "cancelled",
+            StatusKind::Other => "other",
+        };
+        lines.push(format!(
+            "* Status: {}{}",
+            status,
+            self.describe_source(outline, idx, attrs.status.as_ref())
+        ));
+        if let Some(priority) = &attrs.priority {
+            lines.push(format!(
+                "* Priority: {}{}",
+                priority.value,
+                self.describe_source(outline, idx, Some(priority))
+            ));
+        }
+        for (label, date) in ["Start", "Target", "Deadline"].iter().zip(&attrs.dates) {
+            if let Some(date) = date {
+                lines.push(format!(
+                    "* {}: {} ({}, {}){}",
+                    label,
+                    date.value.format("%Y-%m-%d"),
+                    date.value.format("%a"),
+                    relative_date(date.value, today),
+                    self.describe_source(outline, idx, Some(date))
+                ));
+            }
+        }
+        if !attrs.categories.is_empty() {
+            let categories = attrs
+                .categories
+                .iter()
+                .map(|category| {
+                    format!(
+                        "`[{}]`{}",
+                        category.value,
+                        self.describe_source(outline, idx, Some(category))
+                    )
+                })
+                .join(", ");
+            lines.push(format!("* Categories: {}", categories));
+        }
+
+        let title = node.text().unwrap_or_default();
+        let value = format!("**{}**\n\n{}", title, lines.join("\n"));
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value,
+            }),
+            range: node.range.try_pos_into(self),
+        })
+    }
+
+    /// 継承された値であれば、どこから継承されたかの説明を返す。
+    fn describe_source<T>(
+        &self,
+        outline: &Outline,
+        idx: usize,
+        value: Option<&Inherited<T>>,
+    ) -> String {
+        match value {
+            Some(Inherited { source, .. }) if *source != idx => {
+                let source = outline.node(*source);
+                let kind = if source.is_header() {
+                    "header"
+                } else {
+                    "parent task"
+                };
+                format!(
+                    " (from {} at line {}: `{}`)",
+                    kind,
+                    source.row + 1,
+                    self.line(source.row).unwrap_or_default().trim()
+                )
+            }
+            _ => String::new(),
+        }
+    }
+
+    /// カテゴリを（継承を含めて）持つタスクの数を表示する。
+    fn get_category_hover(&self, category: &Category) -> Option<Hover> {
+        let name = category.name();
+        let outline = self.outline();
+        let count = (0..outline.nodes().len())
+            .filter(|&idx| outline.node(idx).is_task())
+            .filter(|&idx| {
+                outline
+                    .attributes(idx)
+                    .categories
+                    .iter()
+                    .any(|c| c.value == name)
+            })
+            .count();
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: format!("Category `[{}]`: used by {} task(s)", name, count),
+            }),
+            range: category.syntax().range().try_pos_into(self),
+        })
+    }
+
+    /// タグを本文に含むタスクの数を表示する。
+    fn get_tag_hover(&self, tag: &Tag) -> Option<Hover> {
+        let name = tag.name();
+        let outline = self.outline();
+        let count = outline
+            .nodes()
+            .iter()
+            .filter(|node| {
+                node.is_task()
+                    && node
+                        .item
+                        .as_task()
+                        .and_then(|task| task.text())
+                        .map(|text| {
+                            text.syntax()
+                                .children_recursive()
+                                .into_iter()
+                                .filter_map(Tag::cast)
+                                .any(|t| t.name() == name)
+                        })
+                        .unwrap_or(false)
+            })
+            .count();
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: format!("Tag `@{}`: used by {} task(s)", name, count),
+            }),
+            range: tag.syntax().range().try_pos_into(self),
+        })
+    }
+}
+
+/// `today` から見た `date` の相対的な表現（"in 3 days", "yesterday" など）。
+fn relative_date(date: NaiveDate, today: NaiveDate) -> String {
+    match (date - today).num_days() {
+        0 => "today".to_owned(),
+        1 => "tomorrow".to_owned(),
+        -1 => "yesterday".to_owned(),
+        n if n > 0 => format!("in {} days", n),
+        n => format!("{} days ago", -n),
+    }
+}
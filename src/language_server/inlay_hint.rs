@@ -0,0 +1,180 @@
+use chrono::NaiveDate;
+use itertools::Itertools;
+use tower_lsp::lsp_types::{InlayHint, InlayHintLabel, InlayHintTooltip, Position, Range};
+use tree_sitter_todome::syntax::ast::{AstNode, Meta};
+
+use crate::{
+    structure::{position::PosInto, syntax::Document},
+    subcmd::format::{format_dates, status_symbol},
+};
+
+impl Document {
+    /// 範囲内のタスクについて、継承している属性と日付までの残り日数を表示する。
+    /// 締切が `upcoming_days` 日以内に迫っていれば強調する。
+    pub fn get_inlay_hints(
+        &self,
+        range: Range,
+        today: NaiveDate,
+        upcoming_days: i64,
+    ) -> Vec<InlayHint> {
+        let outline = self.outline();
+        let rows = (range.start.line as usize)..=(range.end.line as usize);
+        let mut hints = vec![];
+
+        for (idx, node) in outline.nodes().iter().enumerate() {
+            if !node.is_task() || !rows.contains(&node.row) {
+                continue;
+            }
+            let attrs = outline.attributes(idx);
+            let finished = attrs.is_finished();
+
+            // 継承している属性は行頭にまとめて表示する。
+            let mut labels = vec![];
+            let mut sources = vec![];
+            if let Some(status) = attrs.status.as_ref().filter(|s| s.source != idx) {
+                labels.push(status_symbol(status.value).to_owned());
+                sources.push(status.source);
+            }
+            if let Some(priority) = attrs.priority.as_ref().filter(|p| p.source != idx) {
+                labels.push(format!("({})", priority.value));
+                sources.push(priority.source);
+            }
+            let inherited_dates = [0, 1, 2].map(|i| {
+                attrs.dates[i]
+                    .as_ref()
+                    .filter(|date| date.source != idx)
+                    .map(|date| date.value)
+            });
+            if let Some(dates) = format_dates(inherited_dates) {
+                labels.push(dates);
+                sources.extend(attrs.dates.iter().flatten().map(|date| date.source));
+                if !finished {
+                    labels.extend(relative_dates(inherited_dates, today, upcoming_days));
+                }
+            }
+            for category in attrs.categories.iter().filter(|c| c.source != idx) {
+                labels.push(format!("[{}]", category.value));
+                sources.push(category.source);
+            }
+            if !labels.is_empty() {
+                let tooltip = sources
+                    .into_iter()
+                    .filter(|&source| source != idx)
+                    .sorted()
+                    .dedup()
+                    .map(|source| format!("line {}", outline.node(source).row + 1))
+                    .join(", ");
+                if let Some(position) = node.range.0.try_pos_into(self) {
+                    hints.push(make_hint(
+                        position,
+                        labels.join(" "),
+                        Some(format!("Inherited from {}", tooltip)),
+                        false,
+                    ));
+                }
+            }
+
+            // 自身に書かれた日付には、その直後に残り日数を表示する。
+            if finished {
+                continue;
+            }
+            for meta in node.meta() {
+                if let Meta::Date(date) = meta {
+                    let dates = [None, date.target(), date.deadline()];
+                    if let Some(label) = relative_dates(dates, today, upcoming_days) {
+                        let end = date.syntax().range().1;
+                        if let Some(position) = end.try_pos_into(self) {
+                            hints.push(make_hint(position, label, None, true));
+                        }
+                    }
+                }
+            }
+        }
+        hints
+    }
+}
+
+/// 目標日と締切日までの残り日数（"in 3d", "5d overdue" など）。
+fn relative_dates(
+    dates: [Option<NaiveDate>; 3],
+    today: NaiveDate,
+    upcoming_days: i64,
+) -> Option<String> {
+    let target = dates[1].map(|target| relative_date(target, today));
+    let deadline = dates[2].map(|deadline| {
+        let label = relative_date(deadline, today);
+        // 締切が近いことは diagnostics と同じ基準で強調する。
+        if deadline > today && (deadline - today).num_days() <= upcoming_days {
+            format!("due {}!", label)
+        } else {
+            format!("due {}", label)
+        }
+    });
+    let labels = target.into_iter().chain(deadline).collect::<Vec<_>>();
+    if labels.is_empty() {
+        None
+    } else {
+        Some(labels.join(", "))
+    }
+}
+
+fn relative_date(date: NaiveDate, today: NaiveDate) -> String {
+    match (date - today).num_days() {
+        0 => "today".to_owned(),
+        n if n > 0 => format!("in {}d", n),
+        n => format!("{}d overdue", -n),
+    }
+}
+
+fn make_hint(
+    position: Position,
+    label: String,
+    tooltip: Option<String>,
+    padding_left: bool,
+) -> InlayHint {
+    InlayHint {
+        position,
+        label: InlayHintLabel::String(label),
+        kind: None,
+        text_edits: None,
+        tooltip: tooltip.map(InlayHintTooltip::String),
+        padding_left: Some(padding_left),
+        padding_right: Some(!padding_left),
+        data: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::Position;
+
+    use super::*;
+
+    #[test]
+    fn test_inlay_hints() {
+        let today = NaiveDate::from_ymd_opt(2021, 11, 10).unwrap();
+        let document =
+            Document::parse("(2021-11-13!) [work]\n\t(A) task\n\t(2021-11-05) late\n".to_owned())
+                .unwrap();
+        let range = Range::new(Position::new(0, 0), Position::new(3, 0));
+        let hints = document
+            .get_inlay_hints(range, today, 7)
+            .into_iter()
+            .map(|hint| {
+                let label = match hint.label {
+                    InlayHintLabel::String(label) => label,
+                    _ => unreachable!(),
+                };
+                (hint.position.line, hint.position.character, label)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            hints,
+            vec![
+                (1, 1, "(2021-11-13!) due in 3d! [work]".to_owned()),
+                (2, 1, "(2021-11-13!) due in 3d! [work]".to_owned()),
+                (2, 13, "5d overdue".to_owned()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,86 @@
+//! カテゴリやタグの出現箇所の検索。
+
+use tree_sitter_todome::syntax::ast::{AstNode, Category, Tag};
+
+use crate::structure::syntax::Document;
+
+/// 名前で参照されるもの。
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Referent {
+    Category(String),
+    Tag(String),
+}
+
+impl Referent {
+    /// 文書中での書き方（`[name]` や `@name`）。
+    pub fn display(&self) -> String {
+        match self {
+            Referent::Category(name) => format!("[{}]", name),
+            Referent::Tag(name) => format!("@{}", name),
+        }
+    }
+}
+
+/// 出現箇所。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Occurrence {
+    /// カテゴリやタグ全体（括弧や `@` を含む）の範囲。
+    pub range: (usize, usize),
+    /// 名前の部分の範囲。
+    pub name_range: (usize, usize),
+}
+
+impl Document {
+    /// カーソル位置にあるカテゴリやタグを探す。
+    pub fn find_referent(&self, pos: usize) -> Option<(Referent, Occurrence)> {
+        let nodes = self.root().syntax().dig(pos);
+        if let Some(category) = nodes.iter().cloned().find_map(Category::cast) {
+            let range = category.syntax().range();
+            return Some((
+                Referent::Category(category.name()),
+                category_occurrence(range),
+            ));
+        }
+        if let Some(tag) = nodes.iter().cloned().find_map(Tag::cast) {
+            let range = tag.syntax().range();
+            return Some((Referent::Tag(tag.name()), tag_occurrence(range)));
+        }
+        None
+    }
+
+    /// カテゴリやタグの出現箇所を文書順にすべて返す。
+    pub fn find_occurrences(&self, referent: &Referent) -> Vec<Occurrence> {
+        let mut occurrences = self
+            .root()
+            .syntax()
+            .children_recursive()
+            .into_iter()
+            .filter_map(|node| match referent {
+                Referent::Category(name) => Category::cast(node)
+                    .filter(|category| &category.name() == name)
+                    .map(|category| category_occurrence(category.syntax().range())),
+                Referent::Tag(name) => Tag::cast(node)
+                    .filter(|tag| &tag.name() == name)
+                    .map(|tag| tag_occurrence(tag.syntax().range())),
+            })
+            .collect::<Vec<_>>();
+        occurrences.sort_by_key(|occurrence| occurrence.range.0);
+        occurrences
+    }
+}
+
+/// `[name]` の括弧の内側を名前とする。
+fn category_occurrence(range: (usize, usize)) -> Occurrence {
+    Occurrence {
+        range,
+        name_range: (range.0 + 1, range.1 - 1),
+    }
+}
+
+/// `@name` の `@` の後ろを名前とする。
+fn tag_occurrence(range: (usize, usize)) -> Occurrence {
+    Occurrence {
+        range,
+        name_range: (range.0 + 1, range.1),
+    }
+}
@@ -0,0 +1,93 @@
+//! `$/progress` による作業の進捗の通知。
+
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use log::warn;
+use tower_lsp::{
+    lsp_types::{
+        notification::Progress, request::WorkDoneProgressCreate, NumberOrString, ProgressParams,
+        ProgressParamsValue, WorkDoneProgress, WorkDoneProgressBegin, WorkDoneProgressCreateParams,
+        WorkDoneProgressEnd, WorkDoneProgressReport,
+    },
+    Client,
+};
+
+/// token を振るためのカウンタ。
+static NEXT_TOKEN: AtomicU64 = AtomicU64::new(0);
+
+/// 途中経過はこの間隔より頻繁には送らない。
+const REPORT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// サーバ側から始めた進捗。クライアントが対応していなければ何も送らない。
+pub struct WorkDone {
+    client: Client,
+    token: Option<NumberOrString>,
+    last_report: Instant,
+}
+
+impl WorkDone {
+    /// クライアントに token を作らせ、進捗の表示を始める。
+    pub async fn begin(client: Client, supported: bool, title: &str) -> WorkDone {
+        let mut token = None;
+        if supported {
+            let id = NEXT_TOKEN.fetch_add(1, Ordering::Relaxed);
+            let new_token = NumberOrString::String(format!("todome/{}", id));
+            let params = WorkDoneProgressCreateParams {
+                token: new_token.clone(),
+            };
+            match client.send_request::<WorkDoneProgressCreate>(params).await {
+                Ok(()) => token = Some(new_token),
+                Err(e) => warn!("Failed to create progress: {}", e),
+            }
+        }
+        let work_done = WorkDone {
+            client,
+            token,
+            last_report: Instant::now(),
+        };
+        work_done
+            .notify(WorkDoneProgress::Begin(WorkDoneProgressBegin {
+                title: title.to_owned(),
+                cancellable: Some(false),
+                message: None,
+                percentage: Some(0),
+            }))
+            .await;
+        work_done
+    }
+
+    /// 途中経過を送る。前回送ってから [`REPORT_INTERVAL`] 経っていなければ何もしない。
+    pub async fn report(&mut self, message: String, percentage: u32) {
+        if self.last_report.elapsed() < REPORT_INTERVAL {
+            return;
+        }
+        self.last_report = Instant::now();
+        self.notify(WorkDoneProgress::Report(WorkDoneProgressReport {
+            cancellable: Some(false),
+            message: Some(message),
+            percentage: Some(percentage),
+        }))
+        .await;
+    }
+
+    pub async fn end(self, message: String) {
+        self.notify(WorkDoneProgress::End(WorkDoneProgressEnd {
+            message: Some(message),
+        }))
+        .await;
+    }
+
+    async fn notify(&self, value: WorkDoneProgress) {
+        if let Some(token) = &self.token {
+            self.client
+                .send_notification::<Progress>(ProgressParams {
+                    token: token.clone(),
+                    value: ProgressParamsValue::WorkDone(value),
+                })
+                .await;
+        }
+    }
+}
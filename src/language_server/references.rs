@@ -0,0 +1,96 @@
+use std::collections::HashSet;
+
+use itertools::Itertools;
+use tower_lsp::lsp_types::{DocumentHighlight, DocumentHighlightKind, Location, Position, Url};
+
+use crate::structure::{position::PosInto, syntax::Document};
+
+use super::occurrence::Referent;
+
+impl Document {
+    /// カテゴリやタグが使われている箇所。
+    ///
+    /// 出現箇所ごとに 1 つの位置を返す。カテゴリを継承しているタスクがあれば、
+    /// 継承元の行に書かれたカテゴリの位置の代わりに継承元の行全体を指す。
+    pub fn get_references(&self, url: &Url, referent: &Referent) -> Vec<Location> {
+        let outline = self.outline();
+        let mut source_rows = HashSet::new();
+        if let Referent::Category(name) = referent {
+            for idx in 0..outline.nodes().len() {
+                if !outline.node(idx).is_task() {
+                    continue;
+                }
+                let inherited = outline
+                    .attributes(idx)
+                    .categories
+                    .into_iter()
+                    .find(|category| &category.value == name && category.source != idx);
+                if let Some(category) = inherited {
+                    source_rows.insert(outline.node(category.source).row);
+                }
+            }
+        }
+
+        self.find_occurrences(referent)
+            .into_iter()
+            .map(|occurrence| occurrence.range)
+            .map(|range| {
+                let row = self.lines().partition_point(|&p| p <= range.0) - 1;
+                if !source_rows.contains(&row) {
+                    return range;
+                }
+                let start = self.lines()[row];
+                (start, start + self.line(row).unwrap_or_default().len())
+            })
+            .dedup()
+            .filter_map(|range| Some(Location::new(url.clone(), range.try_pos_into(self)?)))
+            .collect()
+    }
+
+    /// カーソル位置のカテゴリやタグと同じものをドキュメント内で強調する。
+    pub fn get_document_highlights(&self, position: Position) -> Option<Vec<DocumentHighlight>> {
+        let pos: usize = position.try_pos_into(self)?;
+        let (referent, _) = self.find_referent(pos)?;
+        let highlights = self
+            .find_occurrences(&referent)
+            .into_iter()
+            .filter_map(|occurrence| {
+                Some(DocumentHighlight {
+                    range: occurrence.range.try_pos_into(self)?,
+                    kind: Some(DocumentHighlightKind::TEXT),
+                })
+            })
+            .collect();
+        Some(highlights)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_references() {
+        let document = Document::parse(
+            "[work] # project\n\ttask @mail\n\t[work] task\ntask [home]\n".to_owned(),
+        )
+        .unwrap();
+        let url = Url::parse("file:///todo.todome").unwrap();
+        let rows = |referent: Referent| {
+            document
+                .get_references(&url, &referent)
+                .into_iter()
+                .map(|location| {
+                    let range = location.range;
+                    (range.start.line, range.start.character, range.end.character)
+                })
+                .collect::<Vec<_>>()
+        };
+        // 2 行目のタスクは 1 行目のヘッダからカテゴリを継承しているので、ヘッダの行全体を指す。
+        assert_eq!(
+            rows(Referent::Category("work".to_owned())),
+            vec![(0, 0, 16), (2, 1, 7)]
+        );
+        assert_eq!(rows(Referent::Tag("mail".to_owned())), vec![(1, 6, 11)]);
+    }
+}
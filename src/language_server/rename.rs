@@ -0,0 +1,108 @@
+use once_cell::sync::Lazy;
+use regex::Regex;
+use tower_lsp::lsp_types::{Position, PrepareRenameResponse, TextEdit};
+
+use crate::structure::{position::PosInto, syntax::Document};
+
+use super::occurrence::Referent;
+
+/// タグの名前（先頭の `@` を除いたもの）。
+static RE_TAG_NAME: Lazy<Regex> = Lazy::new(|| Regex::new(r"^[a-zA-Z0-9][a-zA-Z0-9_-]*$").unwrap());
+
+impl Document {
+    /// カーソル位置のカテゴリやタグの名前の範囲を返す。
+    pub fn get_prepare_rename(&self, position: Position) -> Option<PrepareRenameResponse> {
+        let pos: usize = position.try_pos_into(self)?;
+        let (referent, occurrence) = self.find_referent(pos)?;
+        let placeholder = match referent {
+            Referent::Category(name) | Referent::Tag(name) => name,
+        };
+        Some(PrepareRenameResponse::RangeWithPlaceholder {
+            range: occurrence.name_range.try_pos_into(self)?,
+            placeholder,
+        })
+    }
+
+    /// カテゴリやタグの名前をすべて `new_name` に書き換える編集。
+    pub fn get_rename_edits(&self, referent: &Referent, new_name: &str) -> Vec<TextEdit> {
+        self.find_occurrences(referent)
+            .into_iter()
+            .filter_map(|occurrence| {
+                Some(TextEdit {
+                    range: occurrence.name_range.try_pos_into(self)?,
+                    new_text: new_name.to_owned(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// 新しい名前が正しいか確かめ、書き込むべき名前を返す。
+/// タグの先頭の `@`、カテゴリを囲む `[]` は付けても付けなくてもよい。
+pub fn validate_new_name(referent: &Referent, new_name: &str) -> Result<String, String> {
+    match referent {
+        Referent::Category(_) => {
+            let name = new_name
+                .strip_prefix('[')
+                .and_then(|name| name.strip_suffix(']'))
+                .unwrap_or(new_name)
+                .trim();
+            if new_name.contains(['\n', '\r']) {
+                Err("Category name must not contain line breaks.".to_owned())
+            } else if name.is_empty() {
+                Err("Category name must not be empty.".to_owned())
+            } else if name.contains(['[', ']', '#']) {
+                Err(format!(
+                    "Category name must not contain '[', ']' or '#': {}",
+                    name
+                ))
+            } else {
+                Ok(name.to_owned())
+            }
+        }
+        Referent::Tag(_) => {
+            let name = new_name.strip_prefix('@').unwrap_or(new_name);
+            if RE_TAG_NAME.is_match(name) {
+                Ok(name.to_owned())
+            } else {
+                Err(format!(
+                    "Tag name must match @[a-zA-Z0-9][a-zA-Z0-9_-]*: {}",
+                    new_name
+                ))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_new_name() {
+        let category = Referent::Category("work".to_owned());
+        assert_eq!(
+            validate_new_name(&category, "Project X"),
+            Ok("Project X".to_owned())
+        );
+        assert_eq!(
+            validate_new_name(&category, "[Project Y]"),
+            Ok("Project Y".to_owned())
+        );
+        assert!(validate_new_name(&category, "a#b").is_err());
+        assert!(validate_new_name(&category, "").is_err());
+        assert!(validate_new_name(&category, "a]b").is_err());
+        assert!(validate_new_name(&category, "[a]b]").is_err());
+        assert!(validate_new_name(&category, "a\nb").is_err());
+        assert!(validate_new_name(&category, "a\r\nb").is_err());
+        assert!(validate_new_name(&category, "work\n").is_err());
+
+        let tag = Referent::Tag("email".to_owned());
+        assert_eq!(validate_new_name(&tag, "@mail"), Ok("mail".to_owned()));
+        assert_eq!(validate_new_name(&tag, "mail_2"), Ok("mail_2".to_owned()));
+        assert!(validate_new_name(&tag, "-mail").is_err());
+        assert!(validate_new_name(&tag, "e mail").is_err());
+        assert!(validate_new_name(&tag, "mail\n").is_err());
+        assert!(validate_new_name(&tag, "e\rmail").is_err());
+    }
+}
@@ -0,0 +1,302 @@
+use chrono::NaiveDate;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use tower_lsp::lsp_types::{
+    Position, SemanticToken, SemanticTokenModifier, SemanticTokenType, SemanticTokensEdit,
+    SemanticTokensLegend,
+};
+use tree_sitter_todome::syntax::ast::{AstNode, Item, Meta, StatusKind, Tag};
+
+use crate::structure::{position::PosInto, syntax::Document};
+
+/// トークンの種類。並びは [`legend`] の `token_types` と一致させる。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TokenType {
+    Status,
+    Priority,
+    Date,
+    Category,
+    Tag,
+    Key,
+    Value,
+    Memo,
+}
+
+// トークンの修飾子。ビットの位置は [`legend`] の `token_modifiers` と一致させる。
+const MOD_TODO: u32 = 1 << 0;
+const MOD_DOING: u32 = 1 << 1;
+const MOD_DONE: u32 = 1 << 2;
+const MOD_CANCELLED: u32 = 1 << 3;
+const MOD_HIGH: u32 = 1 << 4;
+const MOD_START: u32 = 1 << 5;
+const MOD_TARGET: u32 = 1 << 6;
+const MOD_DEADLINE: u32 = 1 << 7;
+const MOD_OVERDUE: u32 = 1 << 8;
+const MOD_TODAY: u32 = 1 << 9;
+
+pub fn legend() -> SemanticTokensLegend {
+    SemanticTokensLegend {
+        token_types: vec![
+            SemanticTokenType::KEYWORD,
+            SemanticTokenType::ENUM_MEMBER,
+            SemanticTokenType::NUMBER,
+            SemanticTokenType::NAMESPACE,
+            SemanticTokenType::VARIABLE,
+            SemanticTokenType::PROPERTY,
+            SemanticTokenType::STRING,
+            SemanticTokenType::COMMENT,
+        ],
+        token_modifiers: vec![
+            SemanticTokenModifier::new("todo"),
+            SemanticTokenModifier::new("doing"),
+            SemanticTokenModifier::new("done"),
+            SemanticTokenModifier::new("cancelled"),
+            SemanticTokenModifier::new("high"),
+            SemanticTokenModifier::new("start"),
+            SemanticTokenModifier::new("target"),
+            SemanticTokenModifier::new("deadline"),
+            SemanticTokenModifier::new("overdue"),
+            SemanticTokenModifier::new("today"),
+        ],
+    }
+}
+
+impl Document {
+    /// ドキュメント全体のトークンを求める。`today` は日付の `overdue`, `today` 修飾子の判定に使う。
+    pub fn get_semantic_tokens(&self, today: NaiveDate) -> Vec<SemanticToken> {
+        let outline = self.outline();
+        // (開始位置, 終了位置, 種類, 修飾子)
+        let mut tokens: Vec<(usize, usize, TokenType, u32)> = vec![];
+
+        for (idx, node) in outline.nodes().iter().enumerate() {
+            if let Some(status) = node.status() {
+                let modifier = match status.kind() {
+                    StatusKind::Todo => MOD_TODO,
+                    StatusKind::Doing => MOD_DOING,
+                    StatusKind::Done => MOD_DONE,
+                    StatusKind::Cancel => MOD_CANCELLED,
+                    StatusKind::Other => 0,
+                };
+                let (start, end) = status.syntax().range();
+                tokens.push((start, end, TokenType::Status, modifier));
+            }
+
+            let finished = outline.attributes(idx).is_finished();
+            for meta in node.meta() {
+                match meta {
+                    Meta::Priority(priority) => {
+                        let (start, end) = priority.syntax().range();
+                        if let Some((offset, c)) = self.text()[start..end]
+                            .char_indices()
+                            .find(|(_, c)| c.is_alphanumeric())
+                        {
+                            let modifier = if matches!(c, 'A' | 'B') { MOD_HIGH } else { 0 };
+                            let start = start + offset;
+                            tokens.push((
+                                start,
+                                start + c.len_utf8(),
+                                TokenType::Priority,
+                                modifier,
+                            ));
+                        }
+                    }
+                    Meta::Date(date) => {
+                        let (start, end) = date.syntax().range();
+                        tokens.extend(
+                            date_tokens(&self.text()[start..end], today, finished)
+                                .into_iter()
+                                .map(|(s, e, modifier)| {
+                                    (start + s, start + e, TokenType::Date, modifier)
+                                }),
+                        );
+                    }
+                    Meta::Category(category) => {
+                        let (start, end) = category.syntax().range();
+                        tokens.push((start, end, TokenType::Category, 0));
+                    }
+                    Meta::Keyval(keyval) => {
+                        let (start, end) = keyval.syntax().range();
+                        let text = &self.text()[start..end];
+                        let body_start = start + usize::from(text.starts_with('{'));
+                        let body_end = end - usize::from(text.ends_with('}'));
+                        match self.text()[body_start..body_end].find(':') {
+                            Some(colon) => {
+                                let colon = body_start + colon;
+                                tokens.push((body_start, colon, TokenType::Key, 0));
+                                tokens.push((colon + 1, body_end, TokenType::Value, 0));
+                            }
+                            None => tokens.push((body_start, body_end, TokenType::Key, 0)),
+                        }
+                    }
+                }
+            }
+
+            let memo = match &node.item {
+                Item::Task(task) => task.memo(),
+                Item::Header(header) => header.memo(),
+                Item::Memo(memo) => Some(memo.clone()),
+            };
+            if let Some(memo) = memo {
+                let (start, end) = memo.syntax().range();
+                tokens.push((start, end, TokenType::Memo, 0));
+            }
+
+            if let Some(text) = node.item.as_task().and_then(|task| task.text()) {
+                for tag in text
+                    .syntax()
+                    .children_recursive()
+                    .into_iter()
+                    .filter_map(Tag::cast)
+                {
+                    let (start, end) = tag.syntax().range();
+                    tokens.push((start, end, TokenType::Tag, 0));
+                }
+            }
+        }
+
+        tokens.sort_by_key(|token| token.0);
+        self.encode_tokens(tokens)
+    }
+
+    /// トークンを LSP の相対位置による表現に変換する。複数行にまたがるトークンは捨てる。
+    fn encode_tokens(&self, tokens: Vec<(usize, usize, TokenType, u32)>) -> Vec<SemanticToken> {
+        let mut encoded = vec![];
+        let mut prev = Position::new(0, 0);
+        for (start, end, token_type, modifiers) in tokens {
+            let start: Option<Position> = start.try_pos_into(self);
+            let end: Option<Position> = end.try_pos_into(self);
+            let (start, end) = match (start, end) {
+                (Some(start), Some(end)) if start.line == end.line && start < end => (start, end),
+                _ => continue,
+            };
+            let delta_line = start.line - prev.line;
+            let delta_start = if delta_line == 0 {
+                start.character - prev.character
+            } else {
+                start.character
+            };
+            encoded.push(SemanticToken {
+                delta_line,
+                delta_start,
+                length: end.character - start.character,
+                token_type: token_type as u32,
+                token_modifiers_bitset: modifiers,
+            });
+            prev = start;
+        }
+        encoded
+    }
+}
+
+/// 日付 meta の中の 1 つの日付。
+static RE_DATE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\d{4}-\d{2}-\d{2}").unwrap());
+
+/// 日付 meta のテキスト（`(2021-11-10~2021-11-24 2021-12-01!)` など）から、
+/// 各日付のテキスト内での範囲と修飾子を求める。
+fn date_tokens(text: &str, today: NaiveDate, finished: bool) -> Vec<(usize, usize, u32)> {
+    RE_DATE
+        .find_iter(text)
+        .map(|m| {
+            let role = match text[m.end()..].chars().next() {
+                Some('~') => MOD_START,
+                Some('!') => MOD_DEADLINE,
+                _ => MOD_TARGET,
+            };
+            let mut modifier = role;
+            if let Ok(date) = NaiveDate::parse_from_str(m.as_str(), "%Y-%m-%d") {
+                if date == today {
+                    modifier |= MOD_TODAY;
+                } else if date < today && role != MOD_START && !finished {
+                    modifier |= MOD_OVERDUE;
+                }
+            }
+            (m.start(), m.end(), modifier)
+        })
+        .collect()
+}
+
+/// 前回送ったトークンとの差分を求める。先頭と末尾で一致する部分を除いた 1 つの編集にまとめる。
+pub fn diff_tokens(old: &[SemanticToken], new: &[SemanticToken]) -> Vec<SemanticTokensEdit> {
+    let prefix = old
+        .iter()
+        .zip(new)
+        .take_while(|(old, new)| old == new)
+        .count();
+    if prefix == old.len() && prefix == new.len() {
+        return vec![];
+    }
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(old, new)| old == new)
+        .count();
+    let data = &new[prefix..(new.len() - suffix)];
+    vec![SemanticTokensEdit {
+        start: (prefix * 5) as u32,
+        delete_count: ((old.len() - prefix - suffix) * 5) as u32,
+        data: if data.is_empty() {
+            None
+        } else {
+            Some(data.to_vec())
+        },
+    }]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token(delta_line: u32, delta_start: u32) -> SemanticToken {
+        SemanticToken {
+            delta_line,
+            delta_start,
+            length: 1,
+            token_type: 0,
+            token_modifiers_bitset: 0,
+        }
+    }
+
+    #[test]
+    fn test_date_tokens() {
+        let today = NaiveDate::from_ymd_opt(2021, 11, 10).unwrap();
+        assert_eq!(
+            date_tokens("(2021-11-01~2021-11-10 2021-11-09!)", today, false),
+            vec![
+                (1, 11, MOD_START),
+                (12, 22, MOD_TARGET | MOD_TODAY),
+                (23, 33, MOD_DEADLINE | MOD_OVERDUE),
+            ]
+        );
+        assert_eq!(
+            date_tokens("(2021-11-09)", today, true),
+            vec![(1, 11, MOD_TARGET)]
+        );
+    }
+
+    #[test]
+    fn test_diff_tokens() {
+        let old = vec![token(0, 0), token(1, 0), token(1, 2)];
+        assert_eq!(diff_tokens(&old, &old), vec![]);
+
+        let new = vec![token(0, 0), token(1, 4), token(1, 2)];
+        assert_eq!(
+            diff_tokens(&old, &new),
+            vec![SemanticTokensEdit {
+                start: 5,
+                delete_count: 5,
+                data: Some(vec![token(1, 4)]),
+            }]
+        );
+
+        let new = vec![token(0, 0), token(1, 2)];
+        assert_eq!(
+            diff_tokens(&old, &new),
+            vec![SemanticTokensEdit {
+                start: 5,
+                delete_count: 5,
+                data: None,
+            }]
+        );
+    }
+}
@@ -0,0 +1,238 @@
+use std::collections::HashSet;
+
+use itertools::Itertools;
+use tower_lsp::lsp_types::{
+    DocumentSymbol, Location, SymbolInformation, SymbolKind, SymbolTag, Url,
+};
+use tree_sitter_todome::syntax::ast::{AstNode, Item, Meta, StatusKind, Tag};
+
+use crate::structure::{position::PosInto, syntax::Document};
+
+impl Document {
+    /// ヘッダを名前空間、タスクをその子とした入れ子の symbol を返す。
+    pub fn get_document_symbols(&self) -> Vec<DocumentSymbol> {
+        self.get_document_symbols_of(&self.outline().roots())
+    }
+
+    /// `indices` の各要素の symbol を返す。
+    /// メモは symbol にしないので、代わりにメモの子を直近の祖先の symbol の子として並べる。
+    fn get_document_symbols_of(&self, indices: &[usize]) -> Vec<DocumentSymbol> {
+        indices
+            .iter()
+            .flat_map(|&idx| {
+                let node = self.outline().node(idx);
+                match node.item {
+                    Item::Memo(_) => self.get_document_symbols_of(&node.children),
+                    _ => self.get_document_symbol(idx).into_iter().collect(),
+                }
+            })
+            .collect()
+    }
+
+    fn get_document_symbol(&self, idx: usize) -> Option<DocumentSymbol> {
+        let outline = self.outline();
+        let node = outline.node(idx);
+        let attrs = outline.attributes(idx);
+
+        let (name, kind, detail) = match &node.item {
+            Item::Task(_) => {
+                let status = match attrs.status_kind() {
+                    StatusKind::Todo => "todo",
+                    StatusKind::Doing => "doing",
+                    StatusKind::Done => "done",
+                    StatusKind::Cancel => "cancelled",
+                    StatusKind::Other => "other",
+                };
+                let detail = match &attrs.priority {
+                    Some(priority) => format!("{} ({})", status, priority.value),
+                    None => status.to_owned(),
+                };
+                (node.text().unwrap_or_default(), SymbolKind::EVENT, detail)
+            }
+            Item::Header(_) => {
+                let name = self.line(node.row).unwrap_or_default().trim().to_owned();
+                (name, SymbolKind::NAMESPACE, String::new())
+            }
+            Item::Memo(_) => return None,
+        };
+
+        // 子孫の最後の要素までを symbol の範囲とする。
+        let end = outline
+            .descendants(idx)
+            .last()
+            .map(|&last| outline.node(last).range.1)
+            .unwrap_or(node.range.1);
+        let children = self.get_document_symbols_of(&node.children);
+        let tags = if node.is_task() && attrs.is_finished() {
+            Some(vec![SymbolTag::DEPRECATED])
+        } else {
+            None
+        };
+
+        #[allow(deprecated)]
+        Some(DocumentSymbol {
+            name: if name.is_empty() {
+                "(empty)".to_owned()
+            } else {
+                name
+            },
+            detail: if detail.is_empty() {
+                None
+            } else {
+                Some(detail)
+            },
+            kind,
+            deprecated: tags.as_ref().map(|_| true),
+            tags,
+            range: (node.range.0, end).try_pos_into(self)?,
+            selection_range: node.range.try_pos_into(self)?,
+            children: if children.is_empty() {
+                None
+            } else {
+                Some(children)
+            },
+        })
+    }
+}
+
+impl Document {
+    /// ワークスペース symbol として、タスク・ヘッダ・カテゴリ・タグのうち `query` に合うものを返す。
+    ///
+    /// カテゴリとタグはファイルごとに最初に現れた位置だけを返す。
+    pub fn get_workspace_symbols(&self, url: &Url, query: &str) -> Vec<SymbolInformation> {
+        let outline = self.outline();
+        let file_name = url
+            .path_segments()
+            .and_then(|mut segments| segments.next_back())
+            .unwrap_or_default()
+            .to_owned();
+
+        let mut symbols = vec![];
+        let mut seen = HashSet::new();
+        for (idx, node) in outline.nodes().iter().enumerate() {
+            let container = std::iter::once(file_name.clone())
+                .chain(
+                    outline
+                        .ancestors(idx)
+                        .into_iter()
+                        .rev()
+                        .map(|parent| self.symbol_name(parent)),
+                )
+                .join(" > ");
+            let mut push = |name: String, kind: SymbolKind, range: (usize, usize), tags| {
+                if !fuzzy_match(query, &name) {
+                    return;
+                }
+                if let Some(range) = range.try_pos_into(self) {
+                    #[allow(deprecated)]
+                    symbols.push(SymbolInformation {
+                        name,
+                        kind,
+                        tags,
+                        deprecated: None,
+                        location: Location::new(url.clone(), range),
+                        container_name: Some(container.clone()),
+                    });
+                }
+            };
+
+            match &node.item {
+                Item::Task(task) => {
+                    let tags = if outline.attributes(idx).is_finished() {
+                        Some(vec![SymbolTag::DEPRECATED])
+                    } else {
+                        None
+                    };
+                    push(self.symbol_name(idx), SymbolKind::EVENT, node.range, tags);
+                    let text_tags = task
+                        .text()
+                        .map(|text| {
+                            text.syntax()
+                                .children_recursive()
+                                .into_iter()
+                                .filter_map(Tag::cast)
+                                .collect::<Vec<_>>()
+                        })
+                        .unwrap_or_default();
+                    for tag in text_tags {
+                        let name = format!("@{}", tag.name());
+                        if seen.insert(name.clone()) {
+                            push(name, SymbolKind::KEY, tag.syntax().range(), None);
+                        }
+                    }
+                }
+                Item::Header(_) => {
+                    push(
+                        self.symbol_name(idx),
+                        SymbolKind::NAMESPACE,
+                        node.range,
+                        None,
+                    );
+                }
+                Item::Memo(_) => {}
+            }
+            for meta in node.meta() {
+                if let Meta::Category(category) = meta {
+                    let name = format!("[{}]", category.name());
+                    if seen.insert(name.clone()) {
+                        push(name, SymbolKind::ENUM, category.syntax().range(), None);
+                    }
+                }
+            }
+        }
+        symbols
+    }
+
+    /// タスクは本文、ヘッダは行全体を名前とする。
+    fn symbol_name(&self, idx: usize) -> String {
+        let node = self.outline().node(idx);
+        match node.text() {
+            Some(text) if !text.is_empty() => text,
+            _ => self.line(node.row).unwrap_or_default().trim().to_owned(),
+        }
+    }
+}
+
+/// `query` の文字がこの順に `name` に含まれていれば true（大文字・小文字は区別しない）。
+fn fuzzy_match(query: &str, name: &str) -> bool {
+    let mut chars = name.chars().flat_map(char::to_lowercase);
+    query
+        .chars()
+        .flat_map(char::to_lowercase)
+        .filter(|c| !c.is_whitespace())
+        .all(|q| chars.any(|c| c == q))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fuzzy_match() {
+        assert!(fuzzy_match("", "Buy milk"));
+        assert!(fuzzy_match("bym", "Buy milk"));
+        assert!(fuzzy_match("buy MILK", "Buy milk"));
+        assert!(!fuzzy_match("mb", "Buy milk"));
+        assert!(fuzzy_match("[wo", "[work]"));
+    }
+
+    #[test]
+    fn test_document_symbols() {
+        let document = Document::parse(
+            "[work]\n\ttask A\n\t\tsub A\n\t# memo\n\t\ttask B\n# memo\n\ttask C\n".to_owned(),
+        )
+        .unwrap();
+        let symbols = document.get_document_symbols();
+        let names = |symbols: &[DocumentSymbol]| {
+            symbols
+                .iter()
+                .map(|symbol| symbol.name.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(&symbols), vec!["[work]", "task C"]);
+        let work = symbols[0].children.as_deref().unwrap();
+        assert_eq!(names(work), vec!["task A", "task B"]);
+        assert_eq!(names(work[0].children.as_deref().unwrap()), vec!["sub A"]);
+        assert_eq!(work[1].children, None);
+    }
+}
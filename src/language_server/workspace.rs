@@ -0,0 +1,136 @@
+//! ワークスペース内の `.todome` ファイルの探索と索引。
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use tower_lsp::lsp_types::{InitializeParams, Url};
+
+use crate::structure::syntax::Document;
+
+/// ワークスペース内の `.todome` ファイルを解析したもの。開かれていないファイルの参照に使う。
+#[derive(Debug, Default)]
+pub struct WorkspaceIndex {
+    documents: HashMap<Url, Document>,
+}
+
+impl WorkspaceIndex {
+    pub fn get(&self, url: &Url) -> Option<&Document> {
+        self.documents.get(url)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Url, &Document)> {
+        self.documents.iter()
+    }
+
+    pub fn insert(&mut self, url: Url, document: Document) {
+        self.documents.insert(url, document);
+    }
+
+    pub fn remove(&mut self, url: &Url) -> Option<Document> {
+        self.documents.remove(url)
+    }
+
+    /// `root` 以下のファイルを索引から取り除き、取り除いたファイルを返す。
+    pub fn remove_folder(&mut self, root: &Url) -> Vec<Url> {
+        let root = match root.to_file_path() {
+            Ok(root) => root,
+            Err(_) => return vec![],
+        };
+        let removed = self
+            .documents
+            .keys()
+            .filter(|url| url.to_file_path().is_ok_and(|path| path.starts_with(&root)))
+            .cloned()
+            .collect::<Vec<_>>();
+        for url in &removed {
+            self.documents.remove(url);
+        }
+        removed
+    }
+}
+
+/// ファイルを読んで解析する。
+pub fn load_document(path: &Path) -> anyhow::Result<Document> {
+    let text = fs::read_to_string(path)?;
+    Document::parse(text)
+}
+
+/// 初期化時のパラメータからワークスペースのルートを求める。
+pub fn workspace_roots(params: &InitializeParams) -> Vec<Url> {
+    if let Some(folders) = &params.workspace_folders {
+        folders.iter().map(|folder| folder.uri.clone()).collect()
+    } else {
+        params.root_uri.iter().cloned().collect()
+    }
+}
+
+/// `url` がいずれかのワークスペースのルート以下にあれば true。
+pub fn is_in_workspace(roots: &[Url], url: &Url) -> bool {
+    let path = match url.to_file_path() {
+        Ok(path) => path,
+        Err(_) => return false,
+    };
+    roots
+        .iter()
+        .filter_map(|root| root.to_file_path().ok())
+        .any(|root| path.starts_with(root))
+}
+
+/// `root` 以下にある `.todome` ファイルを再帰的に探す。
+/// 隠しディレクトリと、ループしうるディレクトリへのシンボリックリンクは辿らない。
+pub fn find_todome_files(root: &Path) -> Vec<PathBuf> {
+    let mut files = vec![];
+    let entries = match fs::read_dir(root) {
+        Ok(entries) => entries,
+        Err(_) => return files,
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let hidden = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with('.'));
+        let file_type = match entry.file_type() {
+            Ok(file_type) => file_type,
+            Err(_) => continue,
+        };
+        if hidden {
+            continue;
+        }
+        if file_type.is_dir() {
+            files.extend(find_todome_files(&path));
+        } else if path.extension().is_some_and(|ext| ext == "todome") && path.is_file() {
+            files.push(path);
+        }
+    }
+    files.sort();
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_in_workspace() {
+        let roots = vec![Url::parse("file:///home/user/todo").unwrap()];
+        let url = |s: &str| Url::parse(s).unwrap();
+        assert!(is_in_workspace(
+            &roots,
+            &url("file:///home/user/todo/a.todome")
+        ));
+        assert!(is_in_workspace(
+            &roots,
+            &url("file:///home/user/todo/sub/b.todome")
+        ));
+        assert!(!is_in_workspace(
+            &roots,
+            &url("file:///home/user/todo2/c.todome")
+        ));
+        assert!(!is_in_workspace(&roots, &url("file:///tmp/d.todome")));
+        assert!(!is_in_workspace(&roots, &url("untitled:Untitled-1")));
+    }
+}
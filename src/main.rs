@@ -1,9 +1,10 @@
 use std::{io::Read, path::PathBuf};
 
 use anyhow::*;
+use chrono::NaiveDate;
 
 use clap::{Args, Parser, Subcommand};
-use todome::subcmd::format::format_lines;
+use todome::subcmd::format::{format_lines, FormatOptions, MemoAlignment, TodoStatusStyle};
 
 #[derive(Debug, Clone, Parser)]
 #[clap()]
@@ -15,7 +16,7 @@ struct Opts {
 #[derive(Debug, Clone, Subcommand)]
 enum SubCmd {
     #[clap(alias = "fmt")]
-    Format(InputInfo),
+    Format(FormatArgs),
     Sort(InputInfo),
 }
 
@@ -27,6 +28,39 @@ struct InputInfo {
     inplace: bool,
 }
 
+#[derive(Debug, Clone, Args)]
+struct FormatArgs {
+    #[clap(flatten)]
+    input: InputInfo,
+    /// Align trailing memos (`# ...`) within each sibling block.
+    #[clap(long)]
+    align_memo: bool,
+    /// Base date (YYYY-mm-dd) for expanding date shorthands such as `(tomorrow)`.
+    #[clap(long)]
+    today: Option<NaiveDate>,
+    /// Use LF line endings, drop the BOM and end the file with a newline.
+    #[clap(long)]
+    normalize_line_endings: bool,
+    /// How to write the `+` of todo tasks: keep, explicit or implicit.
+    #[clap(long, default_value = "keep")]
+    todo_status: TodoStatusStyle,
+}
+
+impl FormatArgs {
+    fn options(&self) -> FormatOptions {
+        FormatOptions {
+            memo_alignment: if self.align_memo {
+                MemoAlignment::Aligned
+            } else {
+                MemoAlignment::Compact
+            },
+            today: self.today,
+            normalize_line_endings: self.normalize_line_endings,
+            todo_status: self.todo_status,
+        }
+    }
+}
+
 impl InputInfo {
     fn get_text(&self) -> Result<String> {
         let text = if let Some(input) = &self.input {
@@ -52,10 +86,16 @@ impl InputInfo {
 
 fn main() -> Result<()> {
     match Opts::parse().subcmd {
-        SubCmd::Format(input) => {
-            let text = input.get_text()?;
-            let formatted = format_lines(&text)?;
-            input.save_or_print_text(&formatted)?;
+        SubCmd::Format(args) => {
+            let text = args.input.get_text()?;
+            let formatted = format_lines(&text, &args.options());
+            for line in &formatted.skipped_lines {
+                eprintln!("line {}: syntax error, left unformatted", line + 1);
+            }
+            for (line, message) in &formatted.warnings {
+                eprintln!("line {}: {}", line + 1, message);
+            }
+            args.input.save_or_print_text(&formatted.text)?;
         }
         SubCmd::Sort(input) => {
             let _text = input.get_text()?;
@@ -1,2 +1,3 @@
+pub mod outline;
 pub mod position;
 pub mod syntax;
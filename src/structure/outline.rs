@@ -0,0 +1,248 @@
+//! `SourceFile::items_nested()` の各要素を、インデントに基づいて親子関係のある木にまとめたもの。
+//!
+//! タスクやヘッダの属性（status, 優先度, 日付, カテゴリ）は親から子へと継承される。
+//! 継承を解決した結果は [`Outline::attributes`] で得られる。
+
+use chrono::NaiveDate;
+use tree_sitter_todome::syntax::ast::{AstNode, Item, Meta, SourceFile, Status, StatusKind};
+
+/// 1 つのタスク・ヘッダ・メモ行に対応する節。
+#[derive(Debug, Clone)]
+pub struct OutlineNode {
+    pub item: Item,
+    /// 要素のバイト範囲。
+    pub range: (usize, usize),
+    /// 要素が書かれている行番号（0 始まり）。
+    pub row: usize,
+    /// 行頭の TAB の数。
+    pub indent: usize,
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+}
+
+impl OutlineNode {
+    pub fn is_task(&self) -> bool {
+        matches!(self.item, Item::Task(_))
+    }
+
+    pub fn is_header(&self) -> bool {
+        matches!(self.item, Item::Header(_))
+    }
+
+    pub fn is_memo(&self) -> bool {
+        matches!(self.item, Item::Memo(_))
+    }
+
+    pub fn status(&self) -> Option<Status> {
+        match &self.item {
+            Item::Task(task) => task.status(),
+            Item::Header(header) => header.status(),
+            Item::Memo(_) => None,
+        }
+    }
+
+    pub fn meta(&self) -> Vec<Meta> {
+        match &self.item {
+            Item::Task(task) => task.meta(),
+            Item::Header(header) => header.meta(),
+            Item::Memo(_) => vec![],
+        }
+    }
+
+    /// タスクの本文。タスク以外では `None`。
+    pub fn text(&self) -> Option<String> {
+        match &self.item {
+            Item::Task(task) => task.text().map(|text| text.body().trim().to_owned()),
+            _ => None,
+        }
+    }
+}
+
+/// 継承された（あるいは自身に書かれた）属性の値と、その値が書かれている節。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Inherited<T> {
+    pub value: T,
+    /// 値が書かれている節のインデックス。
+    pub source: usize,
+}
+
+/// 継承を解決した後の属性。
+#[derive(Debug, Clone, Default)]
+pub struct Attributes {
+    pub status: Option<Inherited<StatusKind>>,
+    pub priority: Option<Inherited<String>>,
+    /// 開始日・目標日・締切日。
+    pub dates: [Option<Inherited<NaiveDate>>; 3],
+    pub categories: Vec<Inherited<String>>,
+}
+
+impl Attributes {
+    /// 実質的な status。どこにも書かれていなければ Todo。
+    pub fn status_kind(&self) -> StatusKind {
+        self.status
+            .as_ref()
+            .map(|status| status.value)
+            .unwrap_or(StatusKind::Todo)
+    }
+
+    /// 完了またはキャンセル済みかどうか。
+    pub fn is_finished(&self) -> bool {
+        matches!(self.status_kind(), StatusKind::Done | StatusKind::Cancel)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Outline {
+    nodes: Vec<OutlineNode>,
+}
+
+/// 要素のバイト範囲を返す。
+pub fn item_range(item: &Item) -> (usize, usize) {
+    match item {
+        Item::Task(task) => task.syntax().range(),
+        Item::Header(header) => header.syntax().range(),
+        Item::Memo(memo) => memo.syntax().range(),
+    }
+}
+
+impl Outline {
+    /// 構文木とテキスト、各行の先頭位置から木を組み立てる。
+    pub fn new(root: &SourceFile, text: &str, lines: &[usize]) -> Outline {
+        let mut items = root
+            .items_nested()
+            .into_iter()
+            .map(|item| (item_range(&item), item))
+            .collect::<Vec<_>>();
+        items.sort_by_key(|(range, _)| range.0);
+
+        let mut nodes: Vec<OutlineNode> = vec![];
+        // (indent, 節のインデックス) のスタック。
+        let mut stack: Vec<(usize, usize)> = vec![];
+        for (range, item) in items {
+            let row = match lines.binary_search(&range.0) {
+                Ok(i) => i,
+                Err(i) => i - 1,
+            };
+            let indent = text[lines[row]..range.0]
+                .chars()
+                .filter(|&c| c == '\t')
+                .count();
+            while matches!(stack.last(), Some((i, _)) if *i >= indent) {
+                stack.pop();
+            }
+            let parent = stack.last().map(|(_, idx)| *idx);
+            let idx = nodes.len();
+            if let Some(parent) = parent {
+                nodes[parent].children.push(idx);
+            }
+            stack.push((indent, idx));
+            nodes.push(OutlineNode {
+                item,
+                range,
+                row,
+                indent,
+                parent,
+                children: vec![],
+            });
+        }
+        Outline { nodes }
+    }
+
+    pub fn nodes(&self) -> &[OutlineNode] {
+        &self.nodes
+    }
+
+    pub fn node(&self, idx: usize) -> &OutlineNode {
+        &self.nodes[idx]
+    }
+
+    /// 親を持たない節。
+    pub fn roots(&self) -> Vec<usize> {
+        (0..self.nodes.len())
+            .filter(|&idx| self.nodes[idx].parent.is_none())
+            .collect()
+    }
+
+    /// 指定した行にある節を探す。
+    pub fn find_by_row(&self, row: usize) -> Option<usize> {
+        self.nodes.binary_search_by_key(&row, |node| node.row).ok()
+    }
+
+    /// 祖先の節を近い順に返す。
+    pub fn ancestors(&self, idx: usize) -> Vec<usize> {
+        let mut ancestors = vec![];
+        let mut current = self.nodes[idx].parent;
+        while let Some(parent) = current {
+            ancestors.push(parent);
+            current = self.nodes[parent].parent;
+        }
+        ancestors
+    }
+
+    /// 子孫の節を文書順に返す。
+    pub fn descendants(&self, idx: usize) -> Vec<usize> {
+        let mut descendants = vec![];
+        for &child in &self.nodes[idx].children {
+            descendants.push(child);
+            descendants.extend(self.descendants(child));
+        }
+        descendants
+    }
+
+    /// 子孫のうち最後の行の行番号。子孫が無ければ自身の行番号。
+    pub fn last_row(&self, idx: usize) -> usize {
+        self.descendants(idx)
+            .last()
+            .map(|&last| self.nodes[last].row)
+            .unwrap_or(self.nodes[idx].row)
+    }
+
+    /// 親から継承されるものを含めた属性を求める。
+    ///
+    /// status, 優先度, 日付（開始日・目標日・締切日のそれぞれ）は最も近いところに書かれたものが、
+    /// カテゴリは祖先に書かれたものすべてが有効になる。
+    pub fn attributes(&self, idx: usize) -> Attributes {
+        let mut attrs = Attributes::default();
+        let chain = std::iter::once(idx).chain(self.ancestors(idx));
+        for source in chain {
+            let node = &self.nodes[source];
+            if attrs.status.is_none() {
+                attrs.status = node.status().map(|status| Inherited {
+                    value: status.kind(),
+                    source,
+                });
+            }
+            for meta in node.meta() {
+                match meta {
+                    Meta::Priority(priority) => {
+                        if attrs.priority.is_none() {
+                            attrs.priority = Some(Inherited {
+                                value: priority.value(),
+                                source,
+                            });
+                        }
+                    }
+                    Meta::Date(date) => {
+                        let dates = [date.start(), date.target(), date.deadline()];
+                        for (attr, date) in attrs.dates.iter_mut().zip(dates) {
+                            if attr.is_none() {
+                                *attr = date.map(|value| Inherited { value, source });
+                            }
+                        }
+                    }
+                    Meta::Category(category) => {
+                        let name = category.name();
+                        if !attrs.categories.iter().any(|c| c.value == name) {
+                            attrs.categories.push(Inherited {
+                                value: name,
+                                source,
+                            });
+                        }
+                    }
+                    Meta::Keyval(_) => {}
+                }
+            }
+        }
+        attrs
+    }
+}
@@ -3,21 +3,76 @@
 use std::{collections::HashMap, fmt::Display};
 
 use anyhow::*;
-use tower_lsp::lsp_types::Url;
+use itertools::Itertools;
+use tower_lsp::lsp_types::{TextDocumentContentChangeEvent, Url};
+use tree_sitter::{InputEdit, Parser, Point, Tree};
 use tree_sitter_todome::syntax::ast::{AstNode, SourceFile};
 
+use super::{outline::Outline, position::PosInto};
+
+/// クライアントで開かれているドキュメントと、そのバージョン。
 #[derive(Debug, Clone, Default)]
-pub struct DocumentCache(HashMap<Url, Document>);
+pub struct DocumentCache {
+    documents: HashMap<Url, Document>,
+    versions: HashMap<Url, i32>,
+}
 
 impl DocumentCache {
-    pub fn register_or_update(&mut self, url: &Url, text: String) -> Result<&Document> {
+    pub fn register_or_update(
+        &mut self,
+        url: &Url,
+        text: String,
+        version: i32,
+    ) -> Result<&Document> {
         let document = Document::parse(text)?;
-        self.0.insert(url.to_owned(), document);
-        Ok(self.0.get(url).unwrap())
+        self.documents.insert(url.to_owned(), document);
+        self.versions.insert(url.to_owned(), version);
+        Ok(self.documents.get(url).unwrap())
+    }
+
+    /// 登録済みのドキュメントに変更を順に適用し、最後に 1 度だけ構文木を作り直す。
+    /// 構文木は変更された箇所だけを解析し直す。
+    ///
+    /// 変更が 1 つでも適用できなかった場合や構文木を作り直せなかった場合は、
+    /// クライアントの内容と食い違ったドキュメントから編集を計算しないよう、登録を外してエラーを返す。
+    pub fn apply_changes(
+        &mut self,
+        url: &Url,
+        changes: Vec<TextDocumentContentChangeEvent>,
+        version: i32,
+    ) -> Result<&Document> {
+        let document = self
+            .documents
+            .get_mut(url)
+            .ok_or_else(|| anyhow!("document is not registered: {}", url))?;
+        let result = changes
+            .into_iter()
+            .try_for_each(|change| document.apply_change(change))
+            .and_then(|()| document.reparse());
+        if let Err(e) = result {
+            self.remove(url);
+            return Err(e);
+        }
+        self.versions.insert(url.to_owned(), version);
+        Ok(self.documents.get(url).unwrap())
     }
 
     pub fn get(&self, key: &Url) -> Option<&Document> {
-        self.0.get(key)
+        self.documents.get(key)
+    }
+
+    /// クライアントから最後に受け取ったバージョン。
+    pub fn version(&self, key: &Url) -> Option<i32> {
+        self.versions.get(key).copied()
+    }
+
+    pub fn remove(&mut self, key: &Url) -> Option<Document> {
+        self.versions.remove(key);
+        self.documents.remove(key)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Url, &Document)> {
+        self.documents.iter()
     }
 }
 
@@ -25,7 +80,10 @@ impl DocumentCache {
 pub struct Document {
     text: String,
     lines: Vec<usize>,
+    /// tree-sitter の構文木。編集のたびに変更箇所を伝えておき、解析し直すときに変わっていない部分を使い回す。
+    tree: Tree,
     root: SourceFile,
+    outline: Outline,
 }
 
 /// getter, setter
@@ -40,11 +98,23 @@ impl Document {
         self.text.as_ref()
     }
 
+    /// Get the text of the `row`-th line without its line break.
+    pub fn line(&self, row: usize) -> Option<&str> {
+        let start = *self.lines.get(row)?;
+        let end = self.lines.get(row + 1).copied().unwrap_or(self.text.len());
+        Some(self.text[start..end].trim_end_matches(['\n', '\r']))
+    }
+
     /// Get a reference to the document's body.
     pub fn root(&self) -> &SourceFile {
         &self.root
     }
 
+    /// Get a reference to the document's outline.
+    pub fn outline(&self) -> &Outline {
+        &self.outline
+    }
+
     pub fn into_cst(self) -> SourceFile {
         self.root
     }
@@ -52,11 +122,80 @@ impl Document {
 
 impl Document {
     pub fn parse(text: String) -> Result<Document> {
-        let root = SourceFile::parse(text.clone())?;
+        let tree = parse_tree(&text, None)?;
+        let root = SourceFile::from_tree(&tree, &text)?;
         let mut lines = vec![0usize];
         lines.extend(text.match_indices('\n').map(|(p, _)| p + 1));
-        Ok(Self { text, lines, root })
+        let outline = Outline::new(&root, &text, &lines);
+        Ok(Self {
+            text,
+            lines,
+            tree,
+            root,
+            outline,
+        })
     }
+
+    /// テキストに変更を 1 つ適用する。範囲のない変更はテキスト全体の置き換えとして扱う。
+    ///
+    /// 行頭位置の表は変更された範囲だけを更新し、tree-sitter の構文木には変更箇所を伝える。
+    /// 構文木の解析はしないので、変更をすべて適用した後に `reparse` を呼ぶ必要がある。
+    fn apply_change(&mut self, change: TextDocumentContentChangeEvent) -> Result<()> {
+        let (start, end): (usize, usize) = match change.range {
+            Some(range) => range
+                .try_pos_into(self)
+                .ok_or_else(|| anyhow!("invalid range: {:?}", range))?,
+            None => (0, self.text.len()),
+        };
+        let start_position: Point = start
+            .try_pos_into(self)
+            .ok_or_else(|| anyhow!("invalid position: {}", start))?;
+        let old_end_position: Point = end
+            .try_pos_into(self)
+            .ok_or_else(|| anyhow!("invalid position: {}", end))?;
+        self.text.replace_range(start..end, &change.text);
+
+        // start より後ろ、end 以前にある行頭は消え、新しいテキスト中の改行の直後が行頭になる。
+        // end より後ろの行頭はテキストの長さの変化分だけずれる。
+        let first = self.lines.partition_point(|&p| p <= start);
+        let last = self.lines.partition_point(|&p| p <= end);
+        let inserted = change.text.match_indices('\n').map(|(p, _)| start + p + 1);
+        let shifted = self.lines[last..]
+            .iter()
+            .map(|&p| p + change.text.len() - (end - start));
+        let tail = inserted.chain(shifted).collect_vec();
+        self.lines.splice(first.., tail);
+
+        let new_end = start + change.text.len();
+        let new_end_position: Point = new_end
+            .try_pos_into(self)
+            .ok_or_else(|| anyhow!("invalid position: {}", new_end))?;
+        self.tree.edit(&InputEdit {
+            start_byte: start,
+            old_end_byte: end,
+            new_end_byte: new_end,
+            start_position,
+            old_end_position,
+            new_end_position,
+        });
+        Ok(())
+    }
+
+    fn reparse(&mut self) -> Result<()> {
+        self.tree = parse_tree(&self.text, Some(&self.tree))?;
+        self.root = SourceFile::from_tree(&self.tree, &self.text)?;
+        self.outline = Outline::new(&self.root, &self.text, &self.lines);
+        Ok(())
+    }
+}
+
+/// tree-sitter で構文木を作る。`old_tree` を渡すと、編集されていない部分はそれを使い回す。
+fn parse_tree(text: &str, old_tree: Option<&Tree>) -> Result<Tree> {
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_todome::language())?;
+    parser
+        .parse(text, old_tree)
+        .ok_or_else(|| anyhow!("failed to parse document."))
 }
 
 impl Display for Document {
@@ -64,3 +203,78 @@ impl Display for Document {
         write!(f, "{}", self.root().syntax().display_recursive())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::{Position, Range};
+
+    use super::*;
+
+    #[test]
+    fn test_apply_change() {
+        let mut document =
+            Document::parse("[work]\nmail = 1\n[home]\nshop = 2\n".to_owned()).unwrap();
+        let changes = [
+            ((1, 7), (1, 8), "10\n\tcall = 3"),
+            ((3, 0), (4, 0), ""),
+            ((3, 8), (3, 8), "\ncook = 4"),
+        ];
+        for ((sl, sc), (el, ec), text) in changes {
+            let change = TextDocumentContentChangeEvent {
+                range: Some(Range {
+                    start: Position::new(sl, sc),
+                    end: Position::new(el, ec),
+                }),
+                range_length: None,
+                text: text.to_owned(),
+            };
+            document.apply_change(change).unwrap();
+        }
+        document.reparse().unwrap();
+
+        let expected =
+            Document::parse("[work]\nmail = 10\n\tcall = 3\nshop = 2\ncook = 4\n".to_owned())
+                .unwrap();
+        assert_eq!(document.text(), expected.text());
+        assert_eq!(document.lines(), expected.lines());
+        // 変更箇所を伝えた構文木から解析し直した結果が、最初から解析したものと一致する
+        assert_eq!(tree_nodes(&document.tree), tree_nodes(&expected.tree));
+    }
+
+    /// 構文木の節点の種類と範囲を前順に並べる。
+    fn tree_nodes(tree: &Tree) -> Vec<(String, std::ops::Range<usize>)> {
+        fn walk(node: tree_sitter::Node, nodes: &mut Vec<(String, std::ops::Range<usize>)>) {
+            nodes.push((node.kind().to_owned(), node.byte_range()));
+            let mut cursor = node.walk();
+            for child in node.children(&mut cursor) {
+                walk(child, nodes);
+            }
+        }
+        let mut nodes = vec![];
+        walk(tree.root_node(), &mut nodes);
+        nodes
+    }
+
+    #[test]
+    fn test_apply_changes_with_invalid_range() {
+        let url = Url::parse("file:///test.todome").unwrap();
+        let mut cache = DocumentCache::default();
+        cache
+            .register_or_update(&url, "task A\n".to_owned(), 1)
+            .unwrap();
+        let change = |line, text: &str| TextDocumentContentChangeEvent {
+            range: Some(Range {
+                start: Position::new(line, 0),
+                end: Position::new(line, 0),
+            }),
+            range_length: None,
+            text: text.to_owned(),
+        };
+        let changes = vec![change(1, "task B\n"), change(10, "task C\n")];
+        assert!(cache.apply_changes(&url, changes, 2).is_err());
+
+        // 一部だけ変更を適用した内容は残さない
+        assert!(cache.get(&url).is_none());
+        assert_eq!(cache.version(&url), None);
+    }
+}
@@ -1,24 +1,244 @@
-use std::fmt::Display;
+use std::{collections::HashMap, fmt::Display, str::FromStr};
 
-use chrono::NaiveDate;
+use anyhow::{anyhow, bail};
+use chrono::{Local, NaiveDate};
 use itertools::Itertools;
 use regex::Regex;
-use tree_sitter_todome::syntax::ast::{Item, Memo, Meta, SourceFile, StatusKind, Text};
+use tree_sitter_todome::syntax::ast::{AstNode, Item, Memo, Meta, SourceFile, StatusKind, Text};
+use unicode_width::UnicodeWidthStr;
+
+pub mod shorthand;
+
+/// フォーマットの挙動を決める設定。
+#[derive(Debug, Clone, Default)]
+pub struct FormatOptions {
+    /// 行末のメモ（`# ...`）の配置方法。
+    pub memo_alignment: MemoAlignment,
+    /// `(tomorrow)` などの日付の略記を展開するときの基準日。`None` なら今日。
+    pub today: Option<NaiveDate>,
+    /// 改行コードを LF に揃え、BOM を取り除き、末尾に改行を付ける。
+    /// `false` の場合は元のテキストのものを保つ。
+    pub normalize_line_endings: bool,
+    /// Todo 状態のタスクに `+` を明示するかどうか。
+    pub todo_status: TodoStatusStyle,
+}
+
+/// 行末のメモの配置方法。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MemoAlignment {
+    /// 本文の直後に空白を 1 つだけ挟んでメモを置く。
+    #[default]
+    Compact,
+    /// 兄弟ブロック（ヘッダブロックを含む）ごとにメモの開始位置を揃える。
+    /// 幅は東アジアの全角文字を 2 として数える。
+    Aligned,
+}
+
+/// Todo 状態のタスクにおける `+` の書き方。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TodoStatusStyle {
+    /// 書かれたままにする。
+    #[default]
+    Keep,
+    /// 実質的に Todo であるタスクには常に `+` を付ける。
+    Explicit,
+    /// `+` を取り除く。ただし親から別の status を継承してしまう場合は `+` を残して警告する。
+    Implicit,
+}
+
+impl FromStr for TodoStatusStyle {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "keep" => Ok(TodoStatusStyle::Keep),
+            "explicit" => Ok(TodoStatusStyle::Explicit),
+            "implicit" => Ok(TodoStatusStyle::Implicit),
+            _ => Err(anyhow!(
+                "invalid todo status style: {s} (expected keep, explicit or implicit)"
+            )),
+        }
+    }
+}
+
+/// フォーマットの結果。
+#[derive(Debug, Clone, Default)]
+pub struct Formatted {
+    /// フォーマット後のテキスト。
+    pub text: String,
+    /// 構文エラーを含むため、書かれたまま残した行の行番号（0 始まり）。
+    pub skipped_lines: Vec<usize>,
+    /// 行番号（0 始まり）とフォーマット時の警告の組。
+    pub warnings: Vec<(usize, String)>,
+}
 
 /// 与えられたドキュメントをフォーマットして文字列に変換する。
-pub fn format_lines(text: &str) -> anyhow::Result<String> {
-    let lines = text.lines();
+///
+/// 日付の略記は ISO 形式の日付に展開する。
+/// 構文エラーを含む行はフォーマットせずにそのまま残し、その行番号を `skipped_lines` に記録する。
+/// 改行コード (LF / CRLF)、BOM の有無、末尾の改行の有無は元のテキストに合わせる。
+pub fn format_lines(text: &str, options: &FormatOptions) -> Formatted {
+    let today = options
+        .today
+        .unwrap_or_else(|| Local::now().naive_local().date());
+    let (bom, text) = match text.strip_prefix('\u{feff}') {
+        Some(text) => ("\u{feff}", text),
+        None => ("", text),
+    };
+    let (bom, line_ending, final_newline) = if options.normalize_line_endings {
+        ("", "\n", true)
+    } else {
+        let line_ending = match text.find('\n') {
+            Some(idx) if text[..idx].ends_with('\r') => "\r\n",
+            _ => "\n",
+        };
+        (bom, line_ending, text.ends_with('\n'))
+    };
+    let lines = text.lines().collect_vec();
     let mut todome_lines = vec![];
+    let mut skipped_lines = vec![];
 
-    for line in lines {
-        let mut todome_line = TodomeLine::parse(line)?;
-        todome_line.sort_meta();
-        todome_lines.push(todome_line.stringify());
+    for (idx, line) in lines.iter().enumerate() {
+        match TodomeLine::parse(&shorthand::expand_line(line, today)) {
+            Ok(mut todome_line) => {
+                todome_line.sort_meta();
+                todome_lines.push(Some(todome_line));
+            }
+            Err(_) => {
+                skipped_lines.push(idx);
+                todome_lines.push(None);
+            }
+        }
     }
 
+    let warnings = apply_todo_status_style(&mut todome_lines, options.todo_status);
+
+    let memo_columns = match options.memo_alignment {
+        MemoAlignment::Compact => vec![None; todome_lines.len()],
+        MemoAlignment::Aligned => memo_columns(&todome_lines),
+    };
+
     // 1行ずつフォーマットを掛けていく
+    let body = todome_lines
+        .iter()
+        .zip(memo_columns)
+        .zip(&lines)
+        .map(|((line, column), raw)| match line {
+            Some(line) => line.stringify(column),
+            None => raw.to_string(),
+        })
+        .join(line_ending);
+    let last = if final_newline && !lines.is_empty() {
+        line_ending
+    } else {
+        ""
+    };
+    let text = format!("{bom}{body}{last}");
+
+    Formatted {
+        text,
+        skipped_lines,
+        warnings,
+    }
+}
+
+/// Todo 状態のタスクの `+` を `style` に従って付けたり外したりする。
+///
+/// 親（タスクまたはヘッダ）から継承される status を考慮し、タスクの意味が変わる書き換えはしない。
+/// `+` を外すと意味が変わってしまう行は、そのまま残して警告を返す。
+fn apply_todo_status_style(
+    lines: &mut [Option<TodomeLine>],
+    style: TodoStatusStyle,
+) -> Vec<(usize, String)> {
+    if style == TodoStatusStyle::Keep {
+        return vec![];
+    }
+
+    let mut warnings = vec![];
+    // (indent, その行の実質的な status) のスタック。
+    // フォーマットできなかった行の status は分からないので `None` とする。
+    let mut stack: Vec<(usize, Option<StatusKind>)> = vec![];
+    for (idx, line) in lines.iter_mut().enumerate() {
+        let Some(line) = line else {
+            continue;
+        };
+        if line.body().is_empty() {
+            continue;
+        }
+        while matches!(stack.last(), Some((indent, _)) if *indent >= line.indent) {
+            stack.pop();
+        }
+        let inherited = match stack.last() {
+            Some((_, inherited)) => *inherited,
+            None => Some(StatusKind::Todo),
+        };
+        stack.push((line.indent, line.status.or(inherited)));
+
+        if line.text.is_none() {
+            // ヘッダはタスクではないので書き換えない
+            continue;
+        }
+        match (style, line.status, inherited) {
+            (TodoStatusStyle::Explicit, None, Some(StatusKind::Todo)) => {
+                line.status = Some(StatusKind::Todo);
+            }
+            (TodoStatusStyle::Implicit, Some(StatusKind::Todo), Some(StatusKind::Todo)) => {
+                line.status = None;
+            }
+            (TodoStatusStyle::Implicit, Some(StatusKind::Todo), _) => {
+                warnings.push((
+                    idx,
+                    "kept `+` because removing it would make the task inherit its parent's status"
+                        .to_owned(),
+                ));
+            }
+            _ => {}
+        }
+    }
+    warnings
+}
+
+/// 各行について、メモを置くべき列（インデントを除いた表示幅）を求める。
+///
+/// 同じ親を持ち同じ深さにある連続した行を 1 つのブロックとし、
+/// ブロック内で最も長い本文の直後（空白 1 つ分空けた位置）に揃える。
+/// 空行とフォーマットできなかった行（`None`）はブロックを区切る。
+fn memo_columns(lines: &[Option<TodomeLine>]) -> Vec<Option<usize>> {
+    let mut block_keys = vec![];
+    // (indent, 行番号) のスタック。親の行番号を求めるのに使う。
+    let mut stack: Vec<(usize, usize)> = vec![];
+    let mut segment = 0;
+    for (idx, line) in lines.iter().enumerate() {
+        let Some(line) = line.as_ref().filter(|line| !line.is_blank()) else {
+            segment += 1;
+            block_keys.push(None);
+            continue;
+        };
+        while matches!(stack.last(), Some((indent, _)) if *indent >= line.indent) {
+            stack.pop();
+        }
+        let parent = stack.last().map(|(_, parent)| *parent);
+        stack.push((line.indent, idx));
+        block_keys.push(Some((segment, parent, line.indent)));
+    }
+
+    let mut widths: HashMap<(usize, Option<usize>, usize), usize> = HashMap::new();
+    for (line, key) in lines.iter().zip(&block_keys) {
+        if let (Some(line), Some(key)) = (line, key) {
+            if line.memo.is_some() {
+                let body = line.body();
+                if !body.is_empty() {
+                    let width = widths.entry(*key).or_default();
+                    *width = (*width).max(body.width());
+                }
+            }
+        }
+    }
 
-    Ok(todome_lines.join(""))
+    block_keys
+        .into_iter()
+        .map(|key| widths.get(&key?).map(|width| width + 1))
+        .collect()
 }
 
 #[derive(Debug, Clone)]
@@ -59,52 +279,61 @@ impl Display for MetaData {
         if let Some(priority) = &self.priority {
             write!(f, "({priority}) ")?
         };
-        match self.date {
-            [Some(start), Some(target), Some(deadline)] => {
-                write!(
-                    f,
-                    "({}~{} {}!) ",
-                    start.format("%Y-%m-%d"),
-                    target.format("%Y-%m-%d"),
-                    deadline.format("%Y-%m-%d"),
-                )?;
-            }
-            [None, Some(target), Some(deadline)] => {
-                write!(
-                    f,
-                    "({} {}!) ",
-                    target.format("%Y-%m-%d"),
-                    deadline.format("%Y-%m-%d"),
-                )?;
-            }
-            [Some(start), None, Some(deadline)] => {
-                write!(
-                    f,
-                    "({}~{}!) ",
-                    start.format("%Y-%m-%d"),
-                    deadline.format("%Y-%m-%d"),
-                )?;
-            }
-            [Some(start), Some(target), None] => {
-                write!(
-                    f,
-                    "({}~{}) ",
-                    start.format("%Y-%m-%d"),
-                    target.format("%Y-%m-%d"),
-                )?;
-            }
-            [Some(start), None, None] => write!(f, "({}~) ", start.format("%Y-%m-%d"),)?,
-            [None, Some(target), None] => write!(f, "({}) ", target.format("%Y-%m-%d"),)?,
-            [None, None, Some(deadline)] => write!(f, "({}!) ", deadline.format("%Y-%m-%d"),)?,
-            _ => {}
-        }
+        if let Some(date) = format_dates(self.date) {
+            write!(f, "{date} ")?
+        };
         let cats = self.category.iter().map(|c| format!("[{c}] ")).join("");
         write!(f, "{cats}")?;
         Ok(())
     }
 }
 
+/// status を表す記号。
+pub fn status_symbol(kind: StatusKind) -> &'static str {
+    match kind {
+        StatusKind::Todo => "+",
+        StatusKind::Doing => "*",
+        StatusKind::Done => "-",
+        StatusKind::Cancel => "=",
+        StatusKind::Other => "/",
+    }
+}
+
+/// 開始日・目標日・締切日の組を `(start~target deadline!)` の形式に変換する。
+/// いずれの日付も無い場合は `None` を返す。
+pub fn format_dates(date: [Option<NaiveDate>; 3]) -> Option<String> {
+    let s = match date {
+        [Some(start), Some(target), Some(deadline)] => format!(
+            "({}~{} {}!)",
+            start.format("%Y-%m-%d"),
+            target.format("%Y-%m-%d"),
+            deadline.format("%Y-%m-%d"),
+        ),
+        [None, Some(target), Some(deadline)] => format!(
+            "({} {}!)",
+            target.format("%Y-%m-%d"),
+            deadline.format("%Y-%m-%d"),
+        ),
+        [Some(start), None, Some(deadline)] => format!(
+            "({}~{}!)",
+            start.format("%Y-%m-%d"),
+            deadline.format("%Y-%m-%d"),
+        ),
+        [Some(start), Some(target), None] => format!(
+            "({}~{})",
+            start.format("%Y-%m-%d"),
+            target.format("%Y-%m-%d"),
+        ),
+        [Some(start), None, None] => format!("({}~)", start.format("%Y-%m-%d")),
+        [None, Some(target), None] => format!("({})", target.format("%Y-%m-%d")),
+        [None, None, Some(deadline)] => format!("({}!)", deadline.format("%Y-%m-%d")),
+        [None, None, None] => return None,
+    };
+    Some(s)
+}
+
 impl TodomeLine {
+    /// 1 行をパースする。構文エラーを含む場合は `Err` を返す。
     fn parse(line: &str) -> anyhow::Result<TodomeLine> {
         let re = Regex::new(r#"^\t*"#)?;
         let caps = re.captures(line).unwrap();
@@ -112,6 +341,14 @@ impl TodomeLine {
         let line = line.trim();
 
         let source_file = SourceFile::parse(line.to_owned())?;
+        if source_file
+            .syntax()
+            .children_recursive()
+            .into_iter()
+            .any(|n| n.green().kind().as_str() == "ERROR")
+        {
+            bail!("syntax error: {line}");
+        }
         let item = source_file.items().into_iter().next();
         if item.is_none() {
             return Ok(TodomeLine {
@@ -154,40 +391,57 @@ impl TodomeLine {
         self.meta.sort_by_key(meta_ord);
     }
 
-    fn stringify(&self) -> String {
-        let indent = "\t".repeat(self.indent);
+    fn is_blank(&self) -> bool {
+        self.status.is_none() && self.meta.is_empty() && self.text.is_none() && self.memo.is_none()
+    }
 
+    /// インデントとメモを除いた部分（status, meta, 本文）を文字列にする。
+    fn body(&self) -> String {
         let status = self
             .status
-            .map(|kind| match kind {
-                StatusKind::Todo => "+ ",
-                StatusKind::Doing => "* ",
-                StatusKind::Done => "- ",
-                StatusKind::Cancel => "= ",
-                StatusKind::Other => "/ ",
-            })
+            .map(|kind| format!("{} ", status_symbol(kind)))
             .unwrap_or_default();
 
         let meta = MetaData::from_metas(&self.meta).to_string();
 
-        // contents.push(meta);
         let text: String = self
             .text
             .as_ref()
             .map(|text| text.body().trim().to_owned())
             .unwrap_or_default();
+
+        let content = [meta.trim(), &text]
+            .iter()
+            .filter(|s| !s.is_empty())
+            .join(" ");
+
+        format!("{status}{content}")
+    }
+
+    /// 行を改行を含まない文字列に変換する。
+    /// `memo_column` が与えられた場合、メモはその列（インデントを除いた表示幅）から書き始める。
+    fn stringify(&self, memo_column: Option<usize>) -> String {
+        let indent = "\t".repeat(self.indent);
+        let body = self.body();
+
         let memo: String = self
             .memo
             .as_ref()
             .map(|memo| format!("# {}", memo.body().trim()))
             .unwrap_or_default();
 
-        let content = [meta.trim(), &text, &memo]
-            .iter()
-            .filter(|s| !s.is_empty())
-            .join(" ");
+        if memo.is_empty() {
+            return format!("{indent}{body}");
+        }
+        if body.is_empty() {
+            return format!("{indent}{memo}");
+        }
+        let padding = memo_column
+            .map(|column| column.saturating_sub(body.width()).max(1))
+            .unwrap_or(1);
+        let padding = " ".repeat(padding);
 
-        format!("{indent}{status}{content}\n")
+        format!("{indent}{body}{padding}{memo}")
     }
 }
 
@@ -213,4 +467,74 @@ mod tests {
         dbg!(TodomeLine::parse(r#"(A) # 適当なヘッダ"#).unwrap());
         dbg!(TodomeLine::parse(r#"適当なタスク # コメント"#).unwrap());
     }
+
+    #[test]
+    fn test_align_memo() {
+        let text = "タスク # a\nlonger task # b\n\tsub # c\n\t長いサブタスク # d\nx # e\n";
+        let options = FormatOptions {
+            memo_alignment: MemoAlignment::Aligned,
+            ..Default::default()
+        };
+        let aligned = format_lines(text, &options).text;
+        assert_eq!(
+            aligned,
+            "タスク      # a\nlonger task # b\n\tsub            # c\n\t長いサブタスク # d\nx           # e\n"
+        );
+
+        let collapsed = format_lines(&aligned, &FormatOptions::default()).text;
+        assert_eq!(
+            collapsed,
+            "タスク # a\nlonger task # b\n\tsub # c\n\t長いサブタスク # d\nx # e\n"
+        );
+    }
+
+    #[test]
+    fn test_skip_lines_with_syntax_error() {
+        let text = "(A)   task\n(zzz)   broken   line\n\tsub   # memo\n";
+        let formatted = format_lines(text, &FormatOptions::default());
+        assert_eq!(
+            formatted.text,
+            "(A) task\n(zzz)   broken   line\n\tsub # memo\n"
+        );
+        assert_eq!(formatted.skipped_lines, vec![1]);
+    }
+
+    #[test]
+    fn test_preserve_line_endings() {
+        let text = "\u{feff}(A) task\r\n\tsub task";
+        let formatted = format_lines(text, &FormatOptions::default());
+        assert_eq!(formatted.text, "\u{feff}(A) task\r\n\tsub task");
+
+        let options = FormatOptions {
+            normalize_line_endings: true,
+            ..Default::default()
+        };
+        let formatted = format_lines(text, &options);
+        assert_eq!(formatted.text, "(A) task\n\tsub task\n");
+    }
+
+    #[test]
+    fn test_todo_status_style() {
+        let text = "+ task\ntask\n- parent\n\t+ child\n\tchild\n";
+
+        let options = FormatOptions {
+            todo_status: TodoStatusStyle::Explicit,
+            ..Default::default()
+        };
+        let formatted = format_lines(text, &options);
+        assert_eq!(
+            formatted.text,
+            "+ task\n+ task\n- parent\n\t+ child\n\tchild\n"
+        );
+        assert!(formatted.warnings.is_empty());
+
+        let options = FormatOptions {
+            todo_status: TodoStatusStyle::Implicit,
+            ..Default::default()
+        };
+        let formatted = format_lines(text, &options);
+        assert_eq!(formatted.text, "task\ntask\n- parent\n\t+ child\n\tchild\n");
+        assert_eq!(formatted.warnings.len(), 1);
+        assert_eq!(formatted.warnings[0].0, 3);
+    }
 }
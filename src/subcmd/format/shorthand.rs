@@ -0,0 +1,219 @@
+//! `(tomorrow)` や `(fri~+2w)` のような日付の略記を ISO 形式の日付に展開する。
+//!
+//! 略記は括弧の中身として以下の形で書ける（`MetaData` の `Display` と同じ並び）。
+//!
+//! * `(target)`, `(start~)`, `(start~target)`, `(deadline!)`, `(start~deadline!)`,
+//!   `(target deadline!)`, `(start~target deadline!)`
+//!
+//! それぞれの日付には以下のいずれかを書ける。大文字・小文字は区別しない。
+//!
+//! * `YYYY-mm-dd`
+//! * `today`, `tomorrow`, `yesterday`
+//! * 曜日（`mon`, `friday` など）: 基準日より後で最も近いその曜日
+//! * `next week`（次の月曜日）, `next month`（翌月 1 日）, `next fri`（翌週の金曜日）
+//! * `+3d`, `+2w`, `+1m`, `-1d` などの相対指定
+
+use std::ops::Range;
+
+use chrono::{Datelike, Duration, NaiveDate, Weekday};
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use super::format_dates;
+
+/// 行頭のインデントと status。
+static RE_HEAD: Lazy<Regex> = Lazy::new(|| Regex::new(r"^\t*(?:[+*\-=/] )?").unwrap());
+
+/// 行頭に並ぶ meta の 1 つ。
+static RE_META: Lazy<Regex> = Lazy::new(|| Regex::new(r"^ *(\([^()]*\)|\[[^\[\]]*\])").unwrap());
+
+/// 日付指定の 1 項。
+static RE_TERM: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"^(?:(?P<iso>\d{4}-\d{2}-\d{2})|(?P<rel>[+-]\d+)(?P<unit>[dwm])|next +(?P<next>week|month|[a-z]+)|(?P<word>[a-z]+))",
+    )
+    .unwrap()
+});
+
+/// 1 行のうち、展開できる日付の略記を探す。
+///
+/// 戻り値は行内のバイト範囲（括弧を含む）と、置き換えるべき文字列の組。
+/// 略記を探すのは行頭の status と meta が並ぶ範囲だけで、本文やメモには触れない。
+pub fn find_shorthands(line: &str, today: NaiveDate) -> Vec<(Range<usize>, String)> {
+    let mut pos = RE_HEAD.find(line).map(|m| m.end()).unwrap_or_default();
+    let mut shorthands = vec![];
+    while let Some(caps) = RE_META.captures(&line[pos..]) {
+        let group = caps.get(1).unwrap();
+        let range = (pos + group.start())..(pos + group.end());
+        let inner = &line[(range.start + 1)..(range.end - 1)];
+        if line[range.clone()].starts_with('(') {
+            if let Some(expanded) = expand(inner, today) {
+                if expanded != line[range.clone()] {
+                    shorthands.push((range.clone(), expanded));
+                }
+            }
+        }
+        pos = range.end;
+    }
+    shorthands
+}
+
+/// 1 行に含まれる日付の略記をすべて展開する。
+pub fn expand_line(line: &str, today: NaiveDate) -> String {
+    let mut line = line.to_owned();
+    for (range, expanded) in find_shorthands(&line, today).into_iter().rev() {
+        line.replace_range(range, &expanded);
+    }
+    line
+}
+
+/// 括弧の中身を日付指定として解釈し、`(start~target deadline!)` の形式に変換する。
+/// 日付指定として解釈できない場合（優先度 `(A)` など）は `None` を返す。
+fn expand(inner: &str, today: NaiveDate) -> Option<String> {
+    let inner = inner.trim().to_lowercase();
+    let mut rest = inner.as_str();
+    let mut dates: [Option<NaiveDate>; 3] = [None; 3];
+
+    let first = parse_term(&mut rest, today);
+    if let Some(r) = rest.strip_prefix('~') {
+        rest = r;
+        dates[0] = Some(first?);
+        let second = parse_term(&mut rest, today);
+        if let Some(r) = rest.strip_prefix('!') {
+            rest = r;
+            dates[2] = Some(second?);
+        } else {
+            dates[1] = second;
+            dates[2] = parse_deadline(&mut rest, today);
+        }
+    } else if let Some(r) = rest.strip_prefix('!') {
+        rest = r;
+        dates[2] = Some(first?);
+    } else {
+        dates[1] = Some(first?);
+        dates[2] = parse_deadline(&mut rest, today);
+    }
+
+    if !rest.trim().is_empty() {
+        return None;
+    }
+    format_dates(dates)
+}
+
+/// `rest` の先頭にある ` deadline!` を読む。
+fn parse_deadline(rest: &mut &str, today: NaiveDate) -> Option<NaiveDate> {
+    let mut r = rest.trim_start();
+    let date = parse_term(&mut r, today)?;
+    *rest = r.strip_prefix('!')?;
+    Some(date)
+}
+
+/// `rest` の先頭にある日付を 1 つ読み、読んだ分だけ `rest` を進める。
+fn parse_term(rest: &mut &str, today: NaiveDate) -> Option<NaiveDate> {
+    let caps = RE_TERM.captures(rest)?;
+
+    let date = if let Some(iso) = caps.name("iso") {
+        NaiveDate::parse_from_str(iso.as_str(), "%Y-%m-%d").ok()?
+    } else if let Some(rel) = caps.name("rel") {
+        let n: i64 = rel.as_str().parse().ok()?;
+        match &caps["unit"] {
+            "d" => today.checked_add_signed(Duration::try_days(n)?)?,
+            "w" => today.checked_add_signed(Duration::try_weeks(n)?)?,
+            _ => add_months(today, n)?,
+        }
+    } else if let Some(next) = caps.name("next") {
+        let next_monday = today.checked_add_signed(Duration::days(
+            7 - today.weekday().num_days_from_monday() as i64,
+        ))?;
+        match next.as_str() {
+            "week" => next_monday,
+            "month" => NaiveDate::from_ymd_opt(today.year(), today.month(), 1)
+                .and_then(|first| add_months(first, 1))?,
+            weekday => next_monday.checked_add_signed(Duration::days(
+                parse_weekday(weekday)?.num_days_from_monday() as i64,
+            ))?,
+        }
+    } else {
+        match &caps["word"] {
+            "today" => today,
+            "tomorrow" => today.succ_opt()?,
+            "yesterday" => today.pred_opt()?,
+            word => {
+                let weekday = parse_weekday(word)?;
+                let diff = (weekday.num_days_from_monday() as i64
+                    - today.weekday().num_days_from_monday() as i64)
+                    .rem_euclid(7);
+                today.checked_add_signed(Duration::days(if diff == 0 { 7 } else { diff }))?
+            }
+        }
+    };
+
+    *rest = &rest[caps[0].len()..];
+    Some(date)
+}
+
+fn parse_weekday(s: &str) -> Option<Weekday> {
+    let weekday = match s {
+        "mon" | "monday" => Weekday::Mon,
+        "tue" | "tuesday" => Weekday::Tue,
+        "wed" | "wednesday" => Weekday::Wed,
+        "thu" | "thursday" => Weekday::Thu,
+        "fri" | "friday" => Weekday::Fri,
+        "sat" | "saturday" => Weekday::Sat,
+        "sun" | "sunday" => Weekday::Sun,
+        _ => return None,
+    };
+    Some(weekday)
+}
+
+/// `date` から `months` か月後の日付を求める。日は月末に丸める。
+/// 表せる範囲を超える場合は `None` を返す。
+fn add_months(date: NaiveDate, months: i64) -> Option<NaiveDate> {
+    let total = (date.year() as i64 * 12 + date.month0() as i64).checked_add(months)?;
+    let year = i32::try_from(total.div_euclid(12)).ok()?;
+    let month = total.rem_euclid(12) as u32 + 1;
+    (1..=date.day())
+        .rev()
+        .find_map(|day| NaiveDate::from_ymd_opt(year, month, day))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_line() {
+        // 2021-11-10 は水曜日
+        let today = NaiveDate::from_ymd_opt(2021, 11, 10).unwrap();
+        assert_eq!(expand_line("(tomorrow) task", today), "(2021-11-11) task");
+        assert_eq!(
+            expand_line("- (A) (fri) task", today),
+            "- (A) (2021-11-12) task"
+        );
+        assert_eq!(expand_line("\t(+3d) task", today), "\t(2021-11-13) task");
+        assert_eq!(
+            expand_line("(next week!) task", today),
+            "(2021-11-15!) task"
+        );
+        assert_eq!(
+            expand_line("(today~+2w) task", today),
+            "(2021-11-10~2021-11-24) task"
+        );
+        assert_eq!(
+            expand_line("(today~tomorrow +1m!) task", today),
+            "(2021-11-10~2021-11-11 2021-12-10!) task"
+        );
+        assert_eq!(
+            expand_line("(A) task (tomorrow) # (fri)", today),
+            "(A) task (tomorrow) # (fri)"
+        );
+        // 表せない日付になる略記は展開しない
+        for line in [
+            "(+99999999d) task",
+            "(+9999999999w) task",
+            "(+999999999999m) task",
+        ] {
+            assert_eq!(expand_line(line, today), line);
+        }
+    }
+}